@@ -0,0 +1,30 @@
+// MIDI Spec:
+// https://www.music.mcgill.ca/~ich/classes/mumt306/StandardMIDIfileformat.html
+// https://www.lim.di.unimi.it/IEEE/MIDI/
+
+pub mod parser;
+pub mod writer;
+pub mod timing;
+pub mod sequence;
+
+// The file-level entry points and the data types, the lower-level event parsing stays inside the crate
+pub use parser::{parse_midi_file, parse_midi_file_lossless, parse_midi_file_with_options};
+pub use parser::options::{ParseOptions, ParseMode, ParseWarning, ParseWarningKind};
+pub use parser::chunk::{MidiFile, Header, Track, MidiFileFormat, Division, SMPTEFormat, TrackEvent, TrackEventType, UnknownChunk};
+pub use parser::midi_event::MidiEvent;
+pub use parser::meta_event::MetaEvent;
+pub use parser::borrowed;
+pub use parser::text::{Text, TextEncoding, TextDecoder, detect_encoding};
+pub use parser::key_signature::{KeySignature, Mode, NoteLetter, Accidental, SpelledNote};
+pub use parser::time_signature::{TimeSignature, BeatUnit};
+pub use parser::index::{index_midi_file, ChunkIndex, ChunkEntry, ChunkKind};
+pub use parser::stream::{MidiStreamReader, StreamItem, StreamError};
+pub use parser::error::{ParsingError, ParsingErrorKind, EOFError};
+pub use writer::{write_midi_file, midi_file_to_bytes};
+pub use timing::tempo_map::{TempoMap, TempoChange};
+pub use timing::time_signature_map::{TimeSignatureMap, TimeSignatureChange};
+pub use timing::musical_time::{MusicalTime, MusicalTimeParseError, BarLines, BeatGrid, bar_lines, beat_grid};
+pub use timing::timecode::{SMPTETimecode, start_timecode, track_start_timecode};
+pub use sequence::absolute::{absolute_events, to_delta_events, AbsoluteEvents, DeltaTimeOverflowError, TimedEvents};
+pub use sequence::merge::{merged_events, MergedEvents};
+pub use sequence::notes::{extract_notes, extract_track_notes, Note, NoteOptions, OverlapMode, UnterminatedNoteMode, NoteDiagnostic, Notes};
@@ -1,11 +1,5 @@
 use std::{fs::File, io::Read};
 
-mod parser;
-
-// MIDI Spec:
-// https://www.music.mcgill.ca/~ich/classes/mumt306/StandardMIDIfileformat.html
-// https://www.lim.di.unimi.it/IEEE/MIDI/
-
 fn main() {
     // let path = "Test MIDIs/Megalovania.mid";
     // let path = "Test MIDIs/Nyan Cat.mid";
@@ -24,14 +18,12 @@ fn main() {
         panic!("{}", e);
     }
 
-    let midi_file = match parser::parse_midi_file(&buf) {
+    let midi_file = match midi_parser_rs::parse_midi_file(&buf) {
         Ok(f) => f,
         Err(e) => panic!("{}", e)
     };
 
-    // if let parser::chunk::Chunk::MTrk(trk) = &midi_file.tracks[1] {
-    //     println!("{}", trk.len());
-    // }
+    // println!("{}", midi_file.tracks[1].events().len());
 
     println!("{:#?}", midi_file);
 }
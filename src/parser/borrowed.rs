@@ -0,0 +1,489 @@
+// Events borrowing their data from the parsed buffer, for scanning files without allocating per event.
+// Text meta-events borrow their raw bytes, they are copied into a `Text` when converted to the owned events.
+
+use super::error::*;
+use super::util::*;
+use super::{chunk, midi_event, meta_event, event_parser, text, key_signature, time_signature};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MidiEvent<'a> {
+    // Channel Voice Messages
+    NoteOff {
+        channel: u8,
+        key: u8,
+        velocity: u8
+    },
+    NoteOn {
+        channel: u8,
+        key: u8,
+        velocity: u8
+    },
+    PolyphonicKeyPressure {
+        channel: u8,
+        key: u8,
+        pressure_value: u8
+    },
+    ControlChange {
+        channel: u8,
+        controller_number: u8,
+        new_value: u8
+    },
+    ProgramChange {
+        channel: u8,
+        new_program_number: u8
+    },
+    ChannelPressure {
+        channel: u8,
+        pressure_value: u8
+    },
+    PitchWheelChange {
+        channel: u8,
+        pitch_wheel_value: u16
+    },
+
+    // Channel Mode Messages
+    LocalControlOff {
+        channel: u8
+    },
+    LocalControlOn {
+        channel: u8
+    },
+    AllNotesOff {
+        channel: u8
+    },
+    OmniModeOff {
+        channel: u8
+    },
+    OmniModeOn {
+        channel: u8
+    },
+    MonoModeOn {
+        channel: u8,
+        number_of_channels: u8
+    },
+    PolyModeOn {
+        channel: u8
+    },
+
+    // System Common Messages
+    SystemExclusive {
+        data: &'a [u8]
+    },
+    SystemExclusiveEscape {
+        data: &'a [u8]
+    },
+    SongPositionPointer {
+        midi_beats_since_start: u16
+    },
+    SongSelect {
+        song: u8
+    },
+    TuneRequest,
+
+    // System Real-Time Messages
+    TimingClock,
+    Start,
+    Continue,
+    Stop,
+    ActiveSensing,
+    Reset
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetaEvent<'a> {
+    SequenceNumber {
+        number: u16
+    },
+    TextEvent {
+        text: &'a [u8]
+    },
+    CopyrightNotice {
+        notice: &'a [u8]
+    },
+    TrackName {
+        name: &'a [u8]
+    },
+    InstrumentName {
+        name: &'a [u8]
+    },
+    Lyric {
+        text: &'a [u8]
+    },
+    Marker {
+        name: &'a [u8]
+    },
+    CuePoint {
+        text: &'a [u8]
+    },
+    MIDIChannelPrefix {
+        channel: u8
+    },
+    EndOfTrack,
+    SetTempo {
+        microseconds_per_midi_quarter_note: u64
+    },
+    SMPTEOffset {
+        hour: u8,
+        minute: u8,
+        second: u8,
+        frame: u8,
+        fractional_frames: u8
+    },
+    TimeSignature {
+        signature: time_signature::TimeSignature
+    },
+    InvalidTimeSignature {
+        numerator: u8,
+        denominator_exponent: u8,
+        midi_clocks_per_metronome_click: u8,
+        thirty_second_notes_per_midi_quarter_note: u8
+    },
+    KeySignature {
+        key: key_signature::KeySignature
+    },
+    InvalidKeySignature {
+        sf: u8,
+        mi: u8
+    },
+    SequencerSpecific {
+        data: &'a [u8]
+    },
+    Alien {
+        code: u8,
+        data: &'a [u8]
+    }
+}
+
+impl<'a> MetaEvent<'a> {
+    // The raw bytes of the text meta-events (01-07)
+    pub fn text_bytes(&self) -> Option<&'a [u8]> {
+        match self {
+            Self::TextEvent { text } | Self::Lyric { text } | Self::CuePoint { text } => Some(text),
+            Self::CopyrightNotice { notice } => Some(notice),
+            Self::TrackName { name } | Self::InstrumentName { name } | Self::Marker { name } => Some(name),
+            _ => None
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrackEventType<'a> {
+    Midi(MidiEvent<'a>),
+    Meta(MetaEvent<'a>)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrackEvent<'a> {
+    pub delta_time: u32,
+    pub event: TrackEventType<'a>
+}
+
+impl From<MidiEvent<'_>> for midi_event::MidiEvent {
+    fn from(event: MidiEvent<'_>) -> Self {
+        use MidiEvent::*;
+
+        match event {
+            NoteOff { channel, key, velocity } => Self::NoteOff { channel, key, velocity },
+            NoteOn { channel, key, velocity } => Self::NoteOn { channel, key, velocity },
+            PolyphonicKeyPressure { channel, key, pressure_value } => Self::PolyphonicKeyPressure { channel, key, pressure_value },
+            ControlChange { channel, controller_number, new_value } => Self::ControlChange { channel, controller_number, new_value },
+            ProgramChange { channel, new_program_number } => Self::ProgramChange { channel, new_program_number },
+            ChannelPressure { channel, pressure_value } => Self::ChannelPressure { channel, pressure_value },
+            PitchWheelChange { channel, pitch_wheel_value } => Self::PitchWheelChange { channel, pitch_wheel_value },
+            LocalControlOff { channel } => Self::LocalControlOff { channel },
+            LocalControlOn { channel } => Self::LocalControlOn { channel },
+            AllNotesOff { channel } => Self::AllNotesOff { channel },
+            OmniModeOff { channel } => Self::OmniModeOff { channel },
+            OmniModeOn { channel } => Self::OmniModeOn { channel },
+            MonoModeOn { channel, number_of_channels } => Self::MonoModeOn { channel, number_of_channels },
+            PolyModeOn { channel } => Self::PolyModeOn { channel },
+            SystemExclusive { data } => Self::SystemExclusive { data: data.to_vec() },
+            SystemExclusiveEscape { data } => Self::SystemExclusiveEscape { data: data.to_vec() },
+            SongPositionPointer { midi_beats_since_start } => Self::SongPositionPointer { midi_beats_since_start },
+            SongSelect { song } => Self::SongSelect { song },
+            TuneRequest => Self::TuneRequest,
+            TimingClock => Self::TimingClock,
+            Start => Self::Start,
+            Continue => Self::Continue,
+            Stop => Self::Stop,
+            ActiveSensing => Self::ActiveSensing,
+            Reset => Self::Reset
+        }
+    }
+}
+
+impl From<MetaEvent<'_>> for meta_event::MetaEvent {
+    fn from(event: MetaEvent<'_>) -> Self {
+        use MetaEvent::*;
+
+        let text = text::Text::from;
+
+        match event {
+            SequenceNumber { number } => Self::SequenceNumber { number },
+            TextEvent { text: t } => Self::TextEvent { text: text(t) },
+            CopyrightNotice { notice } => Self::CopyrightNotice { notice: text(notice) },
+            TrackName { name } => Self::TrackName { name: text(name) },
+            InstrumentName { name } => Self::InstrumentName { name: text(name) },
+            Lyric { text: t } => Self::Lyric { text: text(t) },
+            Marker { name } => Self::Marker { name: text(name) },
+            CuePoint { text: t } => Self::CuePoint { text: text(t) },
+            MIDIChannelPrefix { channel } => Self::MIDIChannelPrefix { channel },
+            EndOfTrack => Self::EndOfTrack,
+            SetTempo { microseconds_per_midi_quarter_note } => Self::SetTempo { microseconds_per_midi_quarter_note },
+            SMPTEOffset { hour, minute, second, frame, fractional_frames } => Self::SMPTEOffset { hour, minute, second, frame, fractional_frames },
+            TimeSignature { signature } => Self::TimeSignature { signature },
+            InvalidTimeSignature { numerator, denominator_exponent, midi_clocks_per_metronome_click, thirty_second_notes_per_midi_quarter_note } => {
+                Self::InvalidTimeSignature { numerator, denominator_exponent, midi_clocks_per_metronome_click, thirty_second_notes_per_midi_quarter_note }
+            },
+            KeySignature { key } => Self::KeySignature { key },
+            InvalidKeySignature { sf, mi } => Self::InvalidKeySignature { sf, mi },
+            SequencerSpecific { data } => Self::SequencerSpecific { data: data.to_vec() },
+            Alien { code, data } => Self::Alien { code, data: data.to_vec() }
+        }
+    }
+}
+
+impl From<TrackEventType<'_>> for chunk::TrackEventType {
+    fn from(event: TrackEventType<'_>) -> Self {
+        match event {
+            TrackEventType::Midi(e) => Self::Midi(e.into()),
+            TrackEventType::Meta(e) => Self::Meta(e.into())
+        }
+    }
+}
+
+impl From<TrackEvent<'_>> for chunk::TrackEvent {
+    fn from(event: TrackEvent<'_>) -> Self {
+        Self {
+            delta_time: event.delta_time,
+            event: event.event.into(),
+            encoding: None
+        }
+    }
+}
+
+pub fn parse_midi_event_at<'a>(data: &'a [u8], i: &mut usize) -> Result<MidiEvent<'a>, ParsingError> {
+    event_parser::parse_borrowed_midi_event_with_running_status_at(data, i, &mut None)
+}
+
+pub fn parse_midi_event_with_running_status_at<'a>(data: &'a [u8], i: &mut usize, running_status: &mut Option<u8>) -> Result<MidiEvent<'a>, ParsingError> {
+    event_parser::parse_borrowed_midi_event_with_running_status_at(data, i, running_status)
+}
+
+pub fn parse_meta_event_at<'a>(data: &'a [u8], i: &mut usize) -> Result<MetaEvent<'a>, ParsingError> {
+    event_parser::parse_borrowed_meta_event_at(data, i)
+}
+
+pub fn parse_track_event_at<'a>(data: &'a [u8], i: &mut usize, running_status: &mut Option<u8>) -> Result<TrackEvent<'a>, ParsingError> {
+    let delta_time = match parse_variable_length_at(data, i) {
+        Ok(dt) => dt,
+        Err(e) => return Err(e.with_field("TrackEvent.delta_time"))
+    };
+
+    // Meta-events always start with FF inside of a track, the Reset message can't appear in a file
+    let event = if data.get(*i) == Some(&0xFF) {
+        let meta_event = parse_meta_event_at(data, i)?;
+        // Meta-events cancel any running status
+        *running_status = None;
+        TrackEventType::Meta(meta_event)
+    } else {
+        TrackEventType::Midi(parse_midi_event_with_running_status_at(data, i, running_status)?)
+    };
+
+    Ok(TrackEvent { delta_time, event })
+}
+
+// Iterates over the events of the track data at `data[start..start + length]`, stopping after the first error.
+// The track is checked like by `parse_midi_file`: it has to end with EndOfTrack, exactly at its declared length.
+// The error positions are counted from the start of `data`.
+#[derive(Debug, Clone)]
+pub struct TrackEvents<'a> {
+    data: &'a [u8],
+    // The length of the whole data, the track data is cut at the end of the track
+    data_length: usize,
+    start: usize,
+    length: usize,
+    i: usize,
+    running_status: Option<u8>,
+    event_index: usize,
+    end_of_track: bool,
+    done: bool
+}
+
+impl<'a> TrackEvents<'a> {
+    pub fn new(data: &'a [u8], start: usize, length: usize) -> Self {
+        let end = start.saturating_add(length);
+
+        Self {
+            // The events can't read past the end of the track
+            data: &data[..end.min(data.len())],
+            data_length: data.len(),
+            start,
+            length,
+            i: start,
+            running_status: None,
+            event_index: 0,
+            end_of_track: false,
+            done: false
+        }
+    }
+
+    // Where the next event starts, or where the iteration stopped
+    pub fn position(&self) -> usize {
+        self.i
+    }
+
+    fn fail(&mut self, e: ParsingError) -> Option<Result<TrackEvent<'a>, ParsingError>> {
+        self.done = true;
+        Some(Err(e.at_event(self.event_index)))
+    }
+}
+
+impl<'a> Iterator for TrackEvents<'a> {
+    type Item = Result<TrackEvent<'a>, ParsingError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        if self.i >= self.data.len() {
+            if self.end_of_track {
+                self.done = true;
+                return None;
+            }
+
+            let declared_end = self.start.saturating_add(self.length);
+            let e = if declared_end > self.data_length {
+                ParsingError::eof("MTrk.data", EOFError {
+                    position: self.data_length,
+                    tried_to_read: declared_end - self.data_length,
+                    buffer_size: self.data_length
+                })
+            } else {
+                ParsingError::new(ParsingErrorKind::MissingEndOfTrack, self.i)
+            };
+            return self.fail(e);
+        }
+
+        let event_start = self.i;
+
+        match parse_track_event_at(self.data, &mut self.i, &mut self.running_status) {
+            Ok(_) if self.end_of_track => self.fail(ParsingError::new(ParsingErrorKind::EventAfterEndOfTrack, event_start)),
+            Ok(event) => {
+                self.end_of_track = event.event == TrackEventType::Meta(MetaEvent::EndOfTrack);
+                self.event_index += 1;
+                Some(Ok(event))
+            },
+            Err(e) => {
+                let e = if self.end_of_track {
+                    ParsingError::new(ParsingErrorKind::TrackUnderrun { length: self.length, used: event_start - self.start }, event_start)
+                } else if matches!(e.kind, ParsingErrorKind::UnexpectedEOF { .. }) && self.data.len() < self.data_length {
+                    // The data ended at the end of the track, not of the file
+                    ParsingError::new(ParsingErrorKind::TrackOverrun { length: self.length }, event_start)
+                } else {
+                    e
+                };
+                self.fail(e)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_borrowed_events() {
+        // Track name "Piano", sysex F0 43 12 00 F7, NoteOn, a running status NoteOn, Active Sensing and EndOfTrack
+        let data: [u8; 29] = [
+            0x00, 0xFF, 0x03, 0x05, b'P', b'i', b'a', b'n', b'o',
+            0x00, 0xF0, 0x04, 0x43, 0x12, 0x00, 0xF7,
+            0x00, 0x90, 0x3C, 0x40,
+            0x60, 0x3C, 0x00,
+            0x00, 0xFE,
+            0x00, 0xFF, 0x2F, 0x00
+        ];
+
+        let events: Vec<TrackEvent> = match TrackEvents::new(&data, 0, data.len()).collect() {
+            Ok(e) => e,
+            Err(e) => panic!("{e}")
+        };
+        if events.len() != 6 {
+            panic!("1. fail: {} events", events.len());
+        }
+
+        // The data points into the buffer
+        match events[0].event {
+            TrackEventType::Meta(e @ MetaEvent::TrackName { name }) => if name != b"Piano" || name.as_ptr() != data[4..].as_ptr() || e.text_bytes() != Some(name) {
+                panic!("2. fail: wrong name");
+            },
+            _ => panic!("2. fail: wrong enum variant")
+        }
+        match events[1].event {
+            TrackEventType::Midi(MidiEvent::SystemExclusive { data: d }) => if d.as_ptr() != data[12..].as_ptr() || d.len() != 4 {
+                panic!("3. fail: wrong data");
+            },
+            _ => panic!("3. fail: wrong enum variant")
+        }
+        if events[3] != (TrackEvent { delta_time: 0x60, event: TrackEventType::Midi(MidiEvent::NoteOn { channel: 0, key: 0x3C, velocity: 0 }) }) {
+            panic!("4. fail: running status");
+        }
+
+        let mut i: usize = 0;
+        let owned = match super::super::parse_track_at(&data, &mut i, data.len(), false) {
+            Ok(track) => track.into_events(),
+            _ => panic!("5. fail: owned parsing")
+        };
+        if events.into_iter().map(chunk::TrackEvent::from).collect::<Vec<_>>() != owned {
+            panic!("5. fail: different owned events");
+        }
+
+        // Errors stop the iteration
+        let data2: [u8; 6] = [0x00, 0x90, 0x3C, 0x40, 0x00, 0xF4];
+        let res: Vec<_> = TrackEvents::new(&data2, 0, data2.len()).collect();
+        match res.as_slice() {
+            [Ok(_), Err(e)] => if e.kind != ParsingErrorKind::UnknownStatusByte(0xF4) || e.event_index != Some(1) {
+                panic!("6. fail: wrong error {e}");
+            },
+            _ => panic!("6. fail: wrong results")
+        }
+    }
+
+    #[test]
+    fn test_track_end() {
+        // The same errors as the owned parser
+        let file: [u8; 12] = [0x00, 0x90, 0x3C, 0x40, 0x00, 0xFF, 0x2F, 0x00, 0x00, 0x80, 0x3C, 0x00];
+        for (data, length, expected) in [
+            // An event after EndOfTrack
+            (&file[..], 12, ParsingErrorKind::EventAfterEndOfTrack),
+            // No EndOfTrack
+            (&file[..4], 4, ParsingErrorKind::MissingEndOfTrack),
+            // Data after EndOfTrack that isn't an event
+            (&file[..10], 10, ParsingErrorKind::TrackUnderrun { length: 10, used: 8 }),
+            // NoteOn cut short by the end of the track
+            (&file[..], 3, ParsingErrorKind::TrackOverrun { length: 3 })
+        ] {
+            let res: Result<Vec<TrackEvent>, ParsingError> = TrackEvents::new(data, 0, length).collect();
+            let owned = super::super::parse_track_at(data, &mut 0, length, false);
+            match res {
+                Err(e) => if e.kind != expected || Err(e.clone()) != owned {
+                    panic!("1. fail: wrong error {e}, expected {:?}", expected);
+                },
+                Ok(_) => panic!("1. fail: no error, expected {:?}", expected)
+            }
+        }
+
+        // A track longer than the data
+        let res: Result<Vec<TrackEvent>, ParsingError> = TrackEvents::new(&file[..4], 0, 12).collect();
+        match res {
+            Err(e) => if !matches!(e.kind, ParsingErrorKind::UnexpectedEOF { field: "MTrk.data", .. }) || Err(e.clone()) != super::super::parse_track_at(&file[..4], &mut 0, 12, false) {
+                panic!("2. fail: wrong error {e}");
+            },
+            Ok(_) => panic!("2. fail: no error")
+        }
+    }
+
+}
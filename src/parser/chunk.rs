@@ -1,49 +1,138 @@
 pub const MTHD_LENGTH: usize = 6;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum MidiFileFormat {
     SingleTrack,
     SimultaneousTracks,
     SequentialTracks
 }
 
-#[allow(dead_code)]
-#[derive(Debug)]
+// The frame rate of an SMPTE time division, stored in the header as a negative number.
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SMPTEFormat {
+    Fps24,
+    Fps25,
+    Fps30DropFrame, // 29.97 frames per second
+    Fps30
+}
+
+impl SMPTEFormat {
+    pub fn from_raw(raw: i8) -> Option<Self> {
+        match raw {
+            -24 => Some(Self::Fps24),
+            -25 => Some(Self::Fps25),
+            -29 => Some(Self::Fps30DropFrame),
+            -30 => Some(Self::Fps30),
+            _ => None
+        }
+    }
+
+    pub fn to_raw(self) -> i8 {
+        match self {
+            Self::Fps24 => -24,
+            Self::Fps25 => -25,
+            Self::Fps30DropFrame => -29,
+            Self::Fps30 => -30
+        }
+    }
+
+    // The number of frames labeled in each second, 30 for drop-frame.
+    pub fn nominal_frames_per_second(self) -> u8 {
+        match self {
+            Self::Fps24 => 24,
+            Self::Fps25 => 25,
+            Self::Fps30DropFrame | Self::Fps30 => 30
+        }
+    }
+
+    pub fn frames_per_second(self) -> f64 {
+        match self {
+            Self::Fps30DropFrame => 30000.0 / 1001.0,
+            _ => self.nominal_frames_per_second() as f64
+        }
+    }
+
+    pub fn is_drop_frame(self) -> bool {
+        matches!(self, Self::Fps30DropFrame)
+    }
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Division {
     TicksPerQuarterNote(u16),
-    SMPTE { // Not supported
-        format: u8,
+    SMPTE {
+        format: SMPTEFormat,
         ticks_per_frame: u8
     }
 }
 
-#[derive(Debug)]
+impl Division {
+    pub fn ticks_per_quarter_note(&self) -> Option<u16> {
+        match self {
+            Self::TicksPerQuarterNote(t) => Some(*t),
+            Self::SMPTE { .. } => None
+        }
+    }
+
+    // Only SMPTE divisions have a fixed number of ticks per second, independent of the tempo.
+    pub fn ticks_per_second(&self) -> Option<f64> {
+        match self {
+            Self::TicksPerQuarterNote(_) => None,
+            Self::SMPTE { format, ticks_per_frame } => Some(format.frames_per_second() * *ticks_per_frame as f64)
+        }
+    }
+
+    // The duration of a tick in microseconds, given the current tempo (ignored for SMPTE divisions).
+    pub fn microseconds_per_tick(&self, microseconds_per_midi_quarter_note: u64) -> f64 {
+        match self {
+            Self::TicksPerQuarterNote(t) => microseconds_per_midi_quarter_note as f64 / *t as f64,
+            Self::SMPTE { format, ticks_per_frame } => 1_000_000.0 / (format.frames_per_second() * *ticks_per_frame as f64)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum TrackEventType {
     Midi(super::midi_event::MidiEvent),
     Meta(super::meta_event::MetaEvent)
 }
 
-#[derive(Debug)]
+// How an event was stored in the file, kept by the lossless parsing mode so that it can be written back unchanged.
+// Only the parser creates it, what it holds can change between versions.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct EventEncoding {
+    // The number of bytes of the delta time, more than needed if it wasn't minimally encoded
+    pub(crate) delta_time_size: u8,
+    // Whether the status byte was omitted (running status)
+    pub(crate) running_status: bool,
+    // The number of bytes of the length of a meta-event or a System Exclusive event
+    pub(crate) data_length_size: u8,
+    // The data of a meta-event that can't be reproduced from the parsed event
+    pub(crate) original_data: Option<Vec<u8>>
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct TrackEvent {
     pub delta_time: u32,
-    pub event: TrackEventType
+    pub event: TrackEventType,
+    // Only set by the lossless parsing mode
+    pub encoding: Option<EventEncoding>
 }
 
-#[derive(Debug)]
-pub enum Chunk {
-    // Header
-    MThd {
-        format: MidiFileFormat,
-        number_of_tracks: u16,
-        division: Division
-    },
-    // Track
-    MTrk(Vec<TrackEvent>)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Header {
+    pub format: MidiFileFormat,
+    // The number of tracks as stored in the file, which can differ from the number of MTrk chunks
+    pub number_of_tracks: u16,
+    pub division: Division
 }
 
-impl Chunk {
-    pub fn default_header() -> Self {
-        Self::MThd {
+impl Default for Header {
+    // Used when a file has no MThd chunk
+    fn default() -> Self {
+        Self {
             format: MidiFileFormat::SingleTrack,
             number_of_tracks: 1,
             division: Division::TicksPerQuarterNote(96)
@@ -51,10 +140,107 @@ impl Chunk {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Track {
+    events: Vec<TrackEvent>
+}
+
+impl Track {
+    pub fn new(events: Vec<TrackEvent>) -> Self {
+        Self { events }
+    }
+
+    pub fn events(&self) -> &[TrackEvent] {
+        &self.events
+    }
+
+    pub fn events_mut(&mut self) -> &mut Vec<TrackEvent> {
+        &mut self.events
+    }
+
+    pub fn into_events(self) -> Vec<TrackEvent> {
+        self.events
+    }
+
+    // The first TrackName meta-event of the track (the sequence name in the first track of a format 0 or 1 file)
+    pub fn name(&self) -> Option<&super::text::Text> {
+        self.events.iter().find_map(|e| match &e.event {
+            TrackEventType::Meta(super::meta_event::MetaEvent::TrackName { name }) => Some(name),
+            _ => None
+        })
+    }
+
+    // The tick of the last event, the sum of all delta times
+    pub fn end_tick(&self) -> u64 {
+        self.events.iter().map(|e| e.delta_time as u64).sum()
+    }
+
+    // The time of the last event in seconds, using the tempo map of the file
+    pub fn end_seconds(&self, tempo_map: &crate::timing::tempo_map::TempoMap) -> f64 {
+        tempo_map.ticks_to_seconds(self.end_tick())
+    }
+
+    pub fn has_end_of_track(&self) -> bool {
+        self.events.last().is_some_and(|e| e.event == TrackEventType::Meta(super::meta_event::MetaEvent::EndOfTrack))
+    }
+
+    // The channels of all channel messages in the track, sorted
+    pub fn channels_used(&self) -> Vec<u8> {
+        let mut used = [false; 16];
+        for event in &self.events {
+            if let TrackEventType::Midi(midi_event) = &event.event {
+                if let Some(channel) = midi_event.channel() {
+                    used[(channel & 0x0F) as usize] = true;
+                }
+            }
+        }
+
+        (0..16).filter(|&c| used[c as usize]).collect()
+    }
+}
+
+impl From<Vec<TrackEvent>> for Track {
+    fn from(events: Vec<TrackEvent>) -> Self {
+        Self::new(events)
+    }
+}
+
+// A chunk with a type other than MThd or MTrk, e.g. proprietary data of a sequencer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownChunk {
+    pub id: [u8; 4],
+    pub data: Vec<u8>
+}
+
+// A chunk of the file in the order it was stored in, each variant refers to the next one of MidiFile.header,
+// MidiFile.tracks or MidiFile.unknown_chunks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ChunkEncoding {
+    Header,
+    Track,
+    Unknown
+}
+
+// Everything about the file that isn't part of the parsed header and tracks, kept by the lossless parsing mode.
+// Only the parser creates it, what it holds can change between versions.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct FileEncoding {
+    pub(crate) chunks: Vec<ChunkEncoding>,
+    // The bytes after the first 6 bytes of a longer MThd chunk
+    pub(crate) header_extra_data: Vec<u8>,
+    // Data after the last chunk that doesn't form a chunk itself
+    pub(crate) trailing_data: Vec<u8>
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct MidiFile {
-    pub header: Chunk,
-    pub tracks: Vec<Chunk>
+    pub header: Header,
+    pub tracks: Vec<Track>,
+    // In the order they were stored in, written after the tracks unless the chunk order is known from the encoding
+    pub unknown_chunks: Vec<UnknownChunk>,
+    // Only set by the lossless parsing mode, the writer then keeps the chunk order
+    // and the number of tracks stored in the header
+    pub encoding: Option<FileEncoding>
 }
 
 impl std::fmt::Display for MidiFile {
@@ -62,3 +248,49 @@ impl std::fmt::Display for MidiFile {
         write!(f, "{:#?}", self)
     }
 }
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use super::super::{midi_event::MidiEvent, meta_event::MetaEvent};
+
+    fn event(delta_time: u32, event: TrackEventType) -> TrackEvent {
+        TrackEvent { delta_time, event, encoding: None }
+    }
+
+    #[test]
+    fn test_track_helpers() {
+        let track = Track::new(vec![
+            event(0, TrackEventType::Meta(MetaEvent::TrackName { name: "Bass".into() })),
+            event(0, TrackEventType::Midi(MidiEvent::NoteOn { channel: 9, key: 36, velocity: 100 })),
+            event(96, TrackEventType::Midi(MidiEvent::ControlChange { channel: 2, controller_number: 7, new_value: 100 })),
+            event(0, TrackEventType::Midi(MidiEvent::SystemExclusive { data: vec![0x7E, 0xF7] })),
+            event(96, TrackEventType::Midi(MidiEvent::NoteOff { channel: 9, key: 36, velocity: 0 })),
+            event(0, TrackEventType::Meta(MetaEvent::InstrumentName { name: "Drums".into() })),
+            event(48, TrackEventType::Meta(MetaEvent::EndOfTrack))
+        ]);
+
+        if !track.name().is_some_and(|n| n == "Bass") {
+            panic!("1. fail: wrong name {:?}", track.name());
+        }
+        if track.channels_used() != vec![2, 9] {
+            panic!("2. fail: wrong channels {:?}", track.channels_used());
+        }
+        if track.end_tick() != 240 || !track.has_end_of_track() {
+            panic!("3. fail: wrong end");
+        }
+
+        // 120 BPM, 96 ticks per quarter note
+        let tempo_map = crate::timing::tempo_map::TempoMap::new(Division::TicksPerQuarterNote(96), &[], 240);
+        if track.end_seconds(&tempo_map) != 1.25 {
+            panic!("3. fail: wrong end time {}", track.end_seconds(&tempo_map));
+        }
+
+        let empty = Track::default();
+        if empty.name().is_some() || !empty.channels_used().is_empty() || empty.end_tick() != 0 || empty.has_end_of_track() {
+            panic!("4. fail: empty track");
+        }
+    }
+
+}
@@ -0,0 +1,152 @@
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EOFError {
+    pub position: usize,
+    pub tried_to_read: usize,
+    pub buffer_size: usize
+}
+
+impl std::fmt::Display for EOFError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "EOFError {{\n\tPosition: {}B\n\tTried to read: {}B\n\tBuffer size: {}B\n\tUntil EOF: {}B\n}}", self.position, self.tried_to_read, self.buffer_size, self.buffer_size.saturating_sub(self.position))
+    }
+}
+
+impl std::error::Error for EOFError {}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParsingErrorKind {
+    // Not enough data to read a field, e.g. "MidiEvent[NoteOn].key"
+    UnexpectedEOF {
+        field: &'static str,
+        source: EOFError
+    },
+    // A variable-length quantity longer than 4 bytes
+    InvalidVariableLength,
+    InvalidChunkLength {
+        chunk_type: [u8; 4],
+        expected: usize,
+        found: usize
+    },
+    InvalidHeaderFormat(u16),
+    InvalidSMPTEFormat(i8),
+    // A division of 0 ticks per quarter note or 0 ticks per frame
+    ZeroDivision,
+    // A status byte with an undefined event code (F1, F4, F5, F9 or FD)
+    UnknownStatusByte(u8),
+    // A data byte in place of a status byte, with no running status in effect
+    MissingRunningStatus(u8),
+    // A meta-event that doesn't start with FF
+    InvalidMetaEvent(u8),
+    InvalidMetaLength {
+        code: u8,
+        expected: u32,
+        found: u32
+    },
+    // An event going past the declared length of its track
+    TrackOverrun {
+        length: usize
+    },
+    // Data that isn't an event between EndOfTrack and the declared end of the track
+    TrackUnderrun {
+        length: usize,
+        used: usize
+    },
+    EventAfterEndOfTrack,
+    MissingEndOfTrack,
+    // A file without an MThd chunk
+    MissingHeader,
+    // A second MThd chunk
+    DuplicateHeader,
+    // A thread parsing the track panicked, see `ChunkIndex::parse_tracks_parallel`
+    ThreadPanicked
+}
+
+impl std::fmt::Display for ParsingErrorKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnexpectedEOF { field, source } => write!(f, "Not enough data to read {}\n{}", field, source),
+            Self::InvalidVariableLength => write!(f, "Variable-length quantity longer than 4 bytes"),
+            Self::InvalidChunkLength { chunk_type, expected, found } => write!(f, "The length of {} was not equal the expected one\nExpected: {}B\nFound: {}B", String::from_utf8_lossy(chunk_type), expected, found),
+            Self::InvalidHeaderFormat(format) => write!(f, "Undefined MIDI file format: {}\nThe file format can only be 0, 1 or 2", format),
+            Self::InvalidSMPTEFormat(format) => write!(f, "Undefined SMPTE format: {}\nThe SMPTE format can only be -24, -25, -29 or -30", format),
+            Self::ZeroDivision => write!(f, "The division can't be 0 ticks per quarter note or 0 ticks per frame"),
+            Self::UnknownStatusByte(code) => write!(f, "Midi event code not defined - {} ({:b} | {:X})", code, code, code),
+            Self::MissingRunningStatus(byte) => write!(f, "Data byte found without a running status in effect - {} ({:b} | {:X})", byte, byte, byte),
+            Self::InvalidMetaEvent(byte) => write!(f, "Meta-event code expected to start with FF - {} ({:b} | {:X})", byte, byte, byte),
+            Self::InvalidMetaLength { code, expected, found } => write!(f, "The length of MetaEvent[{:02X}] was not equal the expected one\nExpected: {}B\nFound: {}B", code, expected, found),
+            Self::TrackOverrun { length } => write!(f, "An event goes past the end of the track\nTrack length: {}B", length),
+            Self::TrackUnderrun { length, used } => write!(f, "The track ended with EndOfTrack before its declared length\nExpected: {}B\nFound: {}B", length, used),
+            Self::EventAfterEndOfTrack => write!(f, "An event was found after EndOfTrack"),
+            Self::MissingEndOfTrack => write!(f, "The track doesn't end with EndOfTrack"),
+            Self::MissingHeader => write!(f, "The file has no MThd chunk"),
+            Self::DuplicateHeader => write!(f, "The file has more than one MThd chunk"),
+            Self::ThreadPanicked => write!(f, "The thread parsing the track panicked")
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParsingError {
+    pub kind: ParsingErrorKind,
+    pub position: usize,
+    // The index of the track (counting only MTrk chunks) and of the event in it, if the error occured inside of a track
+    pub track_index: Option<usize>,
+    pub event_index: Option<usize>
+}
+
+impl ParsingError {
+    pub fn new(kind: ParsingErrorKind, position: usize) -> Self {
+        Self {
+            kind,
+            position,
+            track_index: None,
+            event_index: None
+        }
+    }
+
+    pub fn eof(field: &'static str, source: EOFError) -> Self {
+        let position = source.position;
+        Self::new(ParsingErrorKind::UnexpectedEOF { field, source }, position)
+    }
+
+    // Names the field that was being read, for errors coming from the generic reading functions.
+    pub fn with_field(mut self, field: &'static str) -> Self {
+        if let ParsingErrorKind::UnexpectedEOF { field: f, .. } = &mut self.kind {
+            *f = field;
+        }
+        self
+    }
+
+    pub fn at_track(mut self, track_index: usize) -> Self {
+        self.track_index = Some(track_index);
+        self
+    }
+
+    pub fn at_event(mut self, event_index: usize) -> Self {
+        self.event_index = Some(event_index);
+        self
+    }
+}
+
+impl std::fmt::Display for ParsingError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ParsingError {{\n\tPosition: {}B", self.position)?;
+        if let Some(track_index) = self.track_index {
+            write!(f, "\n\tTrack: {}", track_index)?;
+        }
+        if let Some(event_index) = self.event_index {
+            write!(f, "\n\tEvent: {}", event_index)?;
+        }
+        write!(f, "\n\tMessage: \"{}\"\n}}", self.kind)
+    }
+}
+
+impl std::error::Error for ParsingError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            ParsingErrorKind::UnexpectedEOF { source, .. } => Some(source),
+            _ => None
+        }
+    }
+}
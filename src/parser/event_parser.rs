@@ -1,26 +1,31 @@
 use super::util::*;
+use super::error::*;
+#[cfg(test)]
 use super::midi_event;
 use super::meta_event;
+use super::borrowed;
+use super::key_signature;
+use super::time_signature;
 
-fn try_construct_channel_mode_message(channel: u8, controller_number: u8, new_value: u8) -> Option<midi_event::MidiEvent> {
+fn try_construct_channel_mode_message<'a>(channel: u8, controller_number: u8, new_value: u8) -> Option<borrowed::MidiEvent<'a>> {
 
     if controller_number == 126 {
-        return Some(midi_event::MidiEvent::MonoModeOn { channel, number_of_channels: new_value });
+        return Some(borrowed::MidiEvent::MonoModeOn { channel, number_of_channels: new_value });
     }
 
     match (controller_number, new_value) {
-        (122, 0) => Some(midi_event::MidiEvent::LocalControlOff { channel }),
-        (122, 127) => Some(midi_event::MidiEvent::LocalControlOn { channel }),
-        (123, 0) => Some(midi_event::MidiEvent::AllNotesOff { channel }),
-        (124, 0) => Some(midi_event::MidiEvent::OmniModeOff { channel }),
-        (125, 0) => Some(midi_event::MidiEvent::OmniModeOn { channel }),
-        (127, 0) => Some(midi_event::MidiEvent::PolyModeOn { channel }),
+        (122, 0) => Some(borrowed::MidiEvent::LocalControlOff { channel }),
+        (122, 127) => Some(borrowed::MidiEvent::LocalControlOn { channel }),
+        (123, 0) => Some(borrowed::MidiEvent::AllNotesOff { channel }),
+        (124, 0) => Some(borrowed::MidiEvent::OmniModeOff { channel }),
+        (125, 0) => Some(borrowed::MidiEvent::OmniModeOn { channel }),
+        (127, 0) => Some(borrowed::MidiEvent::PolyModeOn { channel }),
 
         _ => None
     }
 }
 
-fn parse_midi_event_channel_voice_message_at(data: &[u8], i: &mut usize, event_code: u8) -> Result<midi_event::MidiEvent, ParsingError> {
+fn parse_midi_event_channel_voice_message_at<'a>(data: &'a [u8], i: &mut usize, event_code: u8) -> Result<borrowed::MidiEvent<'a>, ParsingError> {
     let channel = event_code & 0b1111;
     
     match event_code & 0b11110000 {
@@ -28,74 +33,50 @@ fn parse_midi_event_channel_voice_message_at(data: &[u8], i: &mut usize, event_c
         0b10000000 => {
             let key = match read_bytes_at(data, i, 1) {
                 Ok(k) => k[0],
-                Err(e) => return Err(ParsingError {
-                    position: *i,
-                    message: format!("Not enough data to read MidiEvent[NoteOff].key\n{}", e)
-                })
+                Err(e) => return Err(ParsingError::eof("MidiEvent[NoteOff].key", e))
             };
             let velocity = match read_bytes_at(data, i, 1) {
                 Ok(v) => v[0],
-                Err(e) => return Err(ParsingError {
-                    position: *i,
-                    message: format!("Not enough data to read MidiEvent[NoteOff].velocity\n{}", e)
-                })
+                Err(e) => return Err(ParsingError::eof("MidiEvent[NoteOff].velocity", e))
             };
 
-            Ok(midi_event::MidiEvent::NoteOff { channel, key, velocity })
+            Ok(borrowed::MidiEvent::NoteOff { channel, key, velocity })
         },
 
         0b10010000 => {
             let key = match read_bytes_at(data, i, 1) {
                 Ok(k) => k[0],
-                Err(e) => return Err(ParsingError {
-                    position: *i,
-                    message: format!("Not enough data to read MidiEvent[NoteOn].key\n{}", e)
-                })
+                Err(e) => return Err(ParsingError::eof("MidiEvent[NoteOn].key", e))
             };
             let velocity = match read_bytes_at(data, i, 1) {
                 Ok(v) => v[0],
-                Err(e) => return Err(ParsingError {
-                    position: *i,
-                    message: format!("Not enough data to read MidiEvent[NoteOn].velocity\n{}", e)
-                })
+                Err(e) => return Err(ParsingError::eof("MidiEvent[NoteOn].velocity", e))
             };
 
-            Ok(midi_event::MidiEvent::NoteOn { channel, key, velocity })
+            Ok(borrowed::MidiEvent::NoteOn { channel, key, velocity })
         },
 
         0b10100000 => {
             let key = match read_bytes_at(data, i, 1) {
                 Ok(k) => k[0],
-                Err(e) => return Err(ParsingError {
-                    position: *i,
-                    message: format!("Not enough data to read MidiEvent[PolyphonicKeyPressure].key\n{}", e)
-                })
+                Err(e) => return Err(ParsingError::eof("MidiEvent[PolyphonicKeyPressure].key", e))
             };
             let pressure_value = match read_bytes_at(data, i, 1) {
                 Ok(p) => p[0],
-                Err(e) => return Err(ParsingError {
-                    position: *i,
-                    message: format!("Not enough data to read MidiEvent[PolyphonicKeyPressure].pressure_value\n{}", e)
-                })
+                Err(e) => return Err(ParsingError::eof("MidiEvent[PolyphonicKeyPressure].pressure_value", e))
             };
 
-            Ok(midi_event::MidiEvent::PolyphonicKeyPressure { channel, key, pressure_value })
+            Ok(borrowed::MidiEvent::PolyphonicKeyPressure { channel, key, pressure_value })
         },
 
         0b10110000 => {
             let controller_number = match read_bytes_at(data, i, 1) {
                 Ok(c) => c[0],
-                Err(e) => return Err(ParsingError {
-                    position: *i,
-                    message: format!("Not enough data to read MidiEvent[ControlChange].controller_number\n{}", e)
-                })
+                Err(e) => return Err(ParsingError::eof("MidiEvent[ControlChange].controller_number", e))
             };
             let new_value = match read_bytes_at(data, i, 1) {
                 Ok(v) => v[0],
-                Err(e) => return Err(ParsingError {
-                    position: *i,
-                    message: format!("Not enough data to read MidiEvent[ControlChange].new_value\n{}", e)
-                })
+                Err(e) => return Err(ParsingError::eof("MidiEvent[ControlChange].new_value", e))
             };
 
             let channel_mode_msg_maybe = try_construct_channel_mode_message(channel, controller_number, new_value);
@@ -104,294 +85,279 @@ fn parse_midi_event_channel_voice_message_at(data: &[u8], i: &mut usize, event_c
                 return Ok(msg);
             }
 
-            Ok(midi_event::MidiEvent::ControlChange { channel, controller_number, new_value })
+            Ok(borrowed::MidiEvent::ControlChange { channel, controller_number, new_value })
         },
 
         0b11000000 => {
             let new_program_number = match read_bytes_at(data, i, 1) {
                 Ok(p) => p[0],
-                Err(e) => return Err(ParsingError {
-                    position: *i,
-                    message: format!("Not enough data to read MidiEvent[ProgramChange].new_program_number\n{}", e)
-                })
+                Err(e) => return Err(ParsingError::eof("MidiEvent[ProgramChange].new_program_number", e))
             };
 
-            Ok(midi_event::MidiEvent::ProgramChange { channel, new_program_number })
+            Ok(borrowed::MidiEvent::ProgramChange { channel, new_program_number })
         },
 
         0b11010000 => {
             let pressure_value = match read_bytes_at(data, i, 1) {
                 Ok(p) => p[0],
-                Err(e) => return Err(ParsingError {
-                    position: *i,
-                    message: format!("Not enough data to read MidiEvent[ChannelPressure].pressure_value\n{}", e)
-                })
+                Err(e) => return Err(ParsingError::eof("MidiEvent[ChannelPressure].pressure_value", e))
             };
 
-            Ok(midi_event::MidiEvent::ChannelPressure { channel, pressure_value })
+            Ok(borrowed::MidiEvent::ChannelPressure { channel, pressure_value })
         },
 
         0b11100000 => {
             let pitch_wheel_value = match read_bytes_at(data, i, 2) {
                 Ok(w) => ((w[1] as u16) << 7) | (w[0] as u16),
-                Err(e) => return Err(ParsingError {
-                    position: *i,
-                    message: format!("Not enough data to read MidiEvent[PitchWheelChange].key\n{}", e)
-                })
+                Err(e) => return Err(ParsingError::eof("MidiEvent[PitchWheelChange].key", e))
             };
 
-            Ok(midi_event::MidiEvent::PitchWheelChange { channel, pitch_wheel_value })
+            Ok(borrowed::MidiEvent::PitchWheelChange { channel, pitch_wheel_value })
         },
 
-        code => Err(ParsingError {
-            position: *i,
-            message: format!("Midi event code not defined - {} ({:b} | {:X})", code, code, code)
-        })
+        code => Err(ParsingError::new(ParsingErrorKind::UnknownStatusByte(code), *i))
+    }
+}
+
+// In a MIDI file the System Exclusive data is prefixed by its length instead of being terminated by F7.
+fn read_system_exclusive_data_at<'a>(data: &'a [u8], i: &mut usize, field: &'static str) -> Result<&'a [u8], ParsingError> {
+    let length = match parse_variable_length_at(data, i) {
+        Ok(l) => l,
+        Err(e) => return Err(e.with_field(field))
+    };
+
+    match read_bytes_at(data, i, length as usize) {
+        Ok(d) => Ok(d),
+        Err(e) => Err(ParsingError::eof(field, e))
     }
 }
 
-fn parse_midi_event_system_common_or_real_time_message_at(data: &[u8], i: &mut usize, event_code: u8) -> Result<midi_event::MidiEvent, ParsingError> {
+fn parse_midi_event_system_common_or_real_time_message_at<'a>(data: &'a [u8], i: &mut usize, event_code: u8) -> Result<borrowed::MidiEvent<'a>, ParsingError> {
     match event_code {
 
         // System Common Messages
 
         0b11110000 => {
-            let manufacturer_id = match read_bytes_at(data, i, 1) {
-                Ok(id) => id[0],
-                Err(e) => return Err(ParsingError {
-                    position: *i,
-                    message: format!("Not enough data to read MidiEvent[SystemExclusive].manufacrurer_id\n{}", e)
-                })
-            };
-            let mut event_data = Vec::<u8>::new();
-            loop {
-                let byte = match read_bytes_at(data, i, 1) {
-                    Ok(id) => id[0],
-                    Err(e) => return Err(ParsingError {
-                        position: *i,
-                        message: format!("Not enough data to read MidiEvent.data[{}]\n{}", event_data.len(), e)
-                    })
-                };
-
-                // "End of Exclusive" message
-                if byte == 0b11110111 {
-                    break;
-                }
-
-                event_data.push(byte);
-            };
+            let event_data = read_system_exclusive_data_at(data, i, "MidiEvent[SystemExclusive].data")?;
 
-            Ok(midi_event::MidiEvent::SystemExclusive { manufacturer_id, data: event_data })
+            Ok(borrowed::MidiEvent::SystemExclusive { data: event_data })
         },
 
-        0b11110001 => unreachable!(), // Undefined event code
-
         0b11110010 => {
             let midi_beats_since_start = match read_bytes_at(data, i, 2) {
                 Ok(b) => ((b[1] as u16) << 7) | (b[0] as u16),
-                Err(e) => return Err(ParsingError {
-                    position: *i,
-                    message: format!("Not enough data to read MidiEvent[SongPositionPointer].midi_beats_since_start\n{}", e)
-                })
+                Err(e) => return Err(ParsingError::eof("MidiEvent[SongPositionPointer].midi_beats_since_start", e))
             };
 
-            Ok(midi_event::MidiEvent::SongPositionPointer { midi_beats_since_start })
+            Ok(borrowed::MidiEvent::SongPositionPointer { midi_beats_since_start })
         },
 
         0b11110011 => {
             let song = match read_bytes_at(data, i, 1) {
                 Ok(s) => s[0],
-                Err(e) => return Err(ParsingError {
-                    position: *i,
-                    message: format!("Not enough data to read MidiEvent[SongSelect].song\n{}", e)
-                })
+                Err(e) => return Err(ParsingError::eof("MidiEvent[SongSelect].song", e))
             };
 
-            Ok(midi_event::MidiEvent::SongSelect { song })
+            Ok(borrowed::MidiEvent::SongSelect { song })
         }
 
-        0b11110100 => unreachable!(), // Undefined event code
-
-        0b11110101 => unreachable!(), // Undefined event code
-
         0b11110110 => {
-            Ok(midi_event::MidiEvent::TuneRequest)
+            Ok(borrowed::MidiEvent::TuneRequest)
         },
 
-        0b11110111 => unreachable!(), // End of Exclusive, should be handled in the SystemExclusive branch
+        0b11110111 => {
+            let event_data = read_system_exclusive_data_at(data, i, "MidiEvent[SystemExclusiveEscape].data")?;
+
+            Ok(borrowed::MidiEvent::SystemExclusiveEscape { data: event_data })
+        },
 
         // System Real-Time Messages
 
         0b11111000 => {
-            Ok(midi_event::MidiEvent::TimingClock)
+            Ok(borrowed::MidiEvent::TimingClock)
         },
         
-        0b11111001 => unreachable!(), // Undefined event code
-
         0b11111010 => {
-            Ok(midi_event::MidiEvent::Start)
+            Ok(borrowed::MidiEvent::Start)
         },
 
         0b11111011 => {
-            Ok(midi_event::MidiEvent::Continue)
+            Ok(borrowed::MidiEvent::Continue)
         },
 
         0b11111100 => {
-            Ok(midi_event::MidiEvent::Stop)
+            Ok(borrowed::MidiEvent::Stop)
         },
 
-        0b11111101 => unreachable!(), // Undefined event code
-
         0b11111110 => {
-            Ok(midi_event::MidiEvent::ActiveSensing)
+            Ok(borrowed::MidiEvent::ActiveSensing)
         },
 
         0b11111111 => {
-            Ok(midi_event::MidiEvent::Reset)
+            Ok(borrowed::MidiEvent::Reset)
         }
 
-        code => return Err(ParsingError {
-            position: *i,
-            message: format!("Midi event code not defined - {} ({:b} | {:X})", code, code, code)
-        })
+        // Undefined event codes (F1, F4, F5, F9 and FD)
+        code => Err(ParsingError::new(ParsingErrorKind::UnknownStatusByte(code), *i))
 
     }
 }
 
+// The owned events are built by the track parser from the borrowed ones, these are kept for the tests
+#[cfg(test)]
 pub fn parse_midi_event_at(data: &[u8], i: &mut usize) -> Result<midi_event::MidiEvent, ParsingError> {
-    let event_code = match read_bytes_at(data, i, 1) {
-        Ok(c) => c[0],
-        Err(e) => panic!("Not enough data to read MidiEvent.event_code\n{}", e)
+    parse_midi_event_with_running_status_at(data, i, &mut None)
+}
+
+#[cfg(test)]
+pub fn parse_midi_event_with_running_status_at(data: &[u8], i: &mut usize, running_status: &mut Option<u8>) -> Result<midi_event::MidiEvent, ParsingError> {
+    parse_borrowed_midi_event_with_running_status_at(data, i, running_status).map(midi_event::MidiEvent::from)
+}
+
+pub fn parse_borrowed_midi_event_with_running_status_at<'a>(data: &'a [u8], i: &mut usize, running_status: &mut Option<u8>) -> Result<borrowed::MidiEvent<'a>, ParsingError> {
+    let first_byte = match data.get(*i) {
+        Some(b) => *b,
+        None => return Err(ParsingError::eof("MidiEvent.event_code", EOFError {
+            position: *i,
+            tried_to_read: 1,
+            buffer_size: data.len()
+        }))
+    };
+
+    // A data byte in place of a status byte means the status of the previous Channel Message is reused (running status).
+    let event_code = if first_byte & 0b10000000 == 0 {
+        match running_status {
+            Some(status) => *status,
+            None => return Err(ParsingError::new(ParsingErrorKind::MissingRunningStatus(first_byte), *i))
+        }
+    } else {
+        *i += 1;
+        first_byte
     };
 
     if event_code & 0b11110000 != 0b11110000 { // Channel Voice Messages are only up to 0b1111nnnn
+        *running_status = Some(event_code);
         return parse_midi_event_channel_voice_message_at(data, i, event_code);
     }
-    
+
+    // System Common Messages cancel the running status, System Real-Time Messages don't affect it.
+    if event_code < 0b11111000 {
+        *running_status = None;
+    }
+
     parse_midi_event_system_common_or_real_time_message_at(data, i, event_code)
 }
 
+#[cfg(test)]
 pub fn try_parse_meta_event(data: &[u8], i: &mut usize) -> Option<meta_event::MetaEvent> {
     // A copy of the iterator, the actual iterator is only moved forward when the return value has been determined.
     let mut i_copy = *i;
-    
+
     // Return None if ANY errors occur during parsing.
 
-    let event_code = match read_bytes_at(data, &mut i_copy, 2) {
+    match parse_meta_event_at(data, &mut i_copy) {
+        Ok(event) => {
+            *i = i_copy;
+            Some(event)
+        },
+        Err(_) => None
+    }
+}
+
+fn check_meta_event_length(position: usize, code: u8, expected: u32, found: u32) -> Result<(), ParsingError> {
+    if expected != found {
+        return Err(ParsingError::new(ParsingErrorKind::InvalidMetaLength { code, expected, found }, position));
+    }
+
+    Ok(())
+}
+
+pub fn parse_meta_event_at(data: &[u8], i: &mut usize) -> Result<meta_event::MetaEvent, ParsingError> {
+    parse_borrowed_meta_event_at(data, i).map(meta_event::MetaEvent::from)
+}
+
+pub fn parse_borrowed_meta_event_at<'a>(data: &'a [u8], i: &mut usize) -> Result<borrowed::MetaEvent<'a>, ParsingError> {
+    let event_code = match read_bytes_at(data, i, 2) {
         Ok(header) => {
             if header[0] != 0xFF {
-                return None;
+                return Err(ParsingError::new(ParsingErrorKind::InvalidMetaEvent(header[0]), *i - 2));
             }
             header[1]
         },
-        Err(_) => return None
+        Err(e) => return Err(ParsingError::eof("MetaEvent.event_code", e))
     };
-    let data_length = match parse_variable_length_at(data, &mut i_copy) {
+    let data_length = match parse_variable_length_at(data, i) {
         Ok(v) => v,
-        Err(_) => return None
+        Err(e) => return Err(e.with_field("MetaEvent.length"))
     };
-    let data = match read_bytes_at(data, &mut i_copy, data_length as usize) {
+    let data_start = *i;
+    let data = match read_bytes_at(data, i, data_length as usize) {
         Ok(d) => d,
-        Err(_) => return None
+        Err(e) => return Err(ParsingError::eof("MetaEvent.data", e))
     };
 
     match event_code {
 
         0x00 => {
-            if data_length != 2 {
-                return None;
-            }
+            check_meta_event_length(data_start, event_code, 2, data_length)?;
 
             let number = ((data[0] as u16) << 8) | (data[1] as u16);
 
-            *i = i_copy;
-            Some(meta_event::MetaEvent::SequenceNumber { number })
+            Ok(borrowed::MetaEvent::SequenceNumber { number })
         },
 
         0x01 => {
-            let text = String::from_utf8_lossy(data);
-
-            *i = i_copy;
-            Some(meta_event::MetaEvent::TextEvent { text: text.into() })
+            Ok(borrowed::MetaEvent::TextEvent { text: data })
         },
 
         0x02 => {
-            let notice = String::from_utf8_lossy(data);
-
-            *i = i_copy;
-            Some(meta_event::MetaEvent::CopyrightNotice { notice: notice.into() })
+            Ok(borrowed::MetaEvent::CopyrightNotice { notice: data })
         },
 
         0x03 => {
-            let name = String::from_utf8_lossy(data);
-
-            *i = i_copy;
-            Some(meta_event::MetaEvent::TrackName { name: name.into() })
+            Ok(borrowed::MetaEvent::TrackName { name: data })
         },
 
         0x04 => {
-            let name = String::from_utf8_lossy(data);
-
-            *i = i_copy;
-            Some(meta_event::MetaEvent::InstrumentName { name: name.into() })
+            Ok(borrowed::MetaEvent::InstrumentName { name: data })
         },
 
         0x05 => {
-            let text = String::from_utf8_lossy(data);
-
-            *i = i_copy;
-            Some(meta_event::MetaEvent::Lyric { text: text.into() })
+            Ok(borrowed::MetaEvent::Lyric { text: data })
         },
 
         0x06 => {
-            let name = String::from_utf8_lossy(data);
-
-            *i = i_copy;
-            Some(meta_event::MetaEvent::Marker { name: name.into() })
+            Ok(borrowed::MetaEvent::Marker { name: data })
         },
 
         0x07 => {
-            let text = String::from_utf8_lossy(data);
-
-            *i = i_copy;
-            Some(meta_event::MetaEvent::CuePoint { text: text.into() })
+            Ok(borrowed::MetaEvent::CuePoint { text: data })
         },
 
         0x20 => {
-            if data_length != 1 {
-                return None;
-            }
+            check_meta_event_length(data_start, event_code, 1, data_length)?;
 
             let channel = data[0];
 
-            *i = i_copy;
-            Some(meta_event::MetaEvent::MIDIChannelPrefix { channel })
+            Ok(borrowed::MetaEvent::MIDIChannelPrefix { channel })
         },
 
         0x2F => {
-            if data_length != 0 {
-                return None;
-            }
+            check_meta_event_length(data_start, event_code, 0, data_length)?;
 
-            *i = i_copy;
-            Some(meta_event::MetaEvent::EndOfTrack)
+            Ok(borrowed::MetaEvent::EndOfTrack)
         },
 
         0x51 => {
-            if data_length != 3 {
-                return None;
-            }
+            check_meta_event_length(data_start, event_code, 3, data_length)?;
 
             let microseconds_per_midi_quarter_note = ((data[0] as u64) << 16) | ((data[1] as u64) << 8) | (data[2] as u64);
 
-            *i = i_copy;
-            Some(meta_event::MetaEvent::SetTempo { microseconds_per_midi_quarter_note })
+            Ok(borrowed::MetaEvent::SetTempo { microseconds_per_midi_quarter_note })
         },
 
         0x54 => {
-            if data_length != 5 {
-                return None;
-            }
+            check_meta_event_length(data_start, event_code, 5, data_length)?;
 
             let hour = data[0];
             let minute = data[1];
@@ -399,50 +365,49 @@ pub fn try_parse_meta_event(data: &[u8], i: &mut usize) -> Option<meta_event::Me
             let frame = data[3];
             let fractional_frames = data[4];
 
-            *i = i_copy;
-            Some(meta_event::MetaEvent::SMPTEOffset { hour, minute, second, frame, fractional_frames })
+            Ok(borrowed::MetaEvent::SMPTEOffset { hour, minute, second, frame, fractional_frames })
         },
 
         0x58 => {
-            if data_length != 4 {
-                return None;
-            }
+            check_meta_event_length(data_start, event_code, 4, data_length)?;
 
             let numerator = data[0];
-            let denominator = data[1];
+            let denominator_exponent = data[1];
             let midi_clocks_per_metronome_click = data[2];
             let thirty_second_notes_per_midi_quarter_note = data[3];
 
-            *i = i_copy;
-            Some(meta_event::MetaEvent::TimeSignature { numerator, denominator, midi_clocks_per_metronome_click, thirty_second_notes_per_midi_quarter_note })
+            // Out-of-range values are kept, files using them are otherwise fine
+            match time_signature::TimeSignature::from_raw(numerator, denominator_exponent, midi_clocks_per_metronome_click, thirty_second_notes_per_midi_quarter_note) {
+                Some(signature) => Ok(borrowed::MetaEvent::TimeSignature { signature }),
+                None => Ok(borrowed::MetaEvent::InvalidTimeSignature { numerator, denominator_exponent, midi_clocks_per_metronome_click, thirty_second_notes_per_midi_quarter_note })
+            }
         },
 
         0x59 => {
-            if data_length != 2 {
-                return None;
-            }
+            check_meta_event_length(data_start, event_code, 2, data_length)?;
 
-            let sf = data[0];
-            let mi = data[1] == 1;
+            let (sf, mi) = (data[0], data[1]);
 
-            *i = i_copy;
-            Some(meta_event::MetaEvent::KeySignature { sf, mi })
+            // Out-of-range values are kept, files using them are otherwise fine
+            match key_signature::KeySignature::from_raw(sf, mi) {
+                Some(key) => Ok(borrowed::MetaEvent::KeySignature { key }),
+                None => Ok(borrowed::MetaEvent::InvalidKeySignature { sf, mi })
+            }
         },
 
         0x7F => {
             
-            *i = i_copy;
-            Some(meta_event::MetaEvent::SequencerSpecific { data: data.to_vec() })
+            Ok(borrowed::MetaEvent::SequencerSpecific { data })
         },
 
         code => {
-            *i = i_copy;
-            Some(meta_event::MetaEvent::Alien { code, data: data.to_vec() })
+            Ok(borrowed::MetaEvent::Alien { code, data })
         }
     }
 }
 
 #[cfg(test)]
+#[allow(clippy::char_lit_as_u8, clippy::nonminimal_bool)]
 mod test {
 
     use super::*;
@@ -987,6 +952,159 @@ mod test {
         }
     }
 
+    #[test]
+    fn test_running_status() {
+        // 1001nnnn 0kkkkkkk 0vvvvvvv, followed by 0kkkkkkk 0vvvvvvv
+
+        let mut i: usize = 0;
+        let mut running_status: Option<u8> = None;
+
+        let data1: [u8; 5] = [0b10010011, 0b00110000, 0b01111111, 0b00110100, 0b00000000];
+        let result1 = match parse_midi_event_with_running_status_at(&data1, &mut i, &mut running_status) {
+            Ok(r) => r,
+            Err(e) => panic!("{e}")
+        };
+        if let midi_event::MidiEvent::NoteOn { channel, key, velocity } = result1 {
+            if !(channel == 3 && key == 48 && velocity == 127) || running_status != Some(0b10010011) {
+                panic!("1. fail: wrong parameters");
+            }
+        } else {
+            panic!("1. fail: wrong enum variant");
+        }
+
+        let result2 = match parse_midi_event_with_running_status_at(&data1, &mut i, &mut running_status) {
+            Ok(r) => r,
+            Err(e) => panic!("{e}")
+        };
+        if let midi_event::MidiEvent::NoteOn { channel, key, velocity } = result2 {
+            if !(channel == 3 && key == 52 && velocity == 0) || i != 5 {
+                panic!("2. fail: wrong parameters");
+            }
+        } else {
+            panic!("2. fail: wrong enum variant");
+        }
+        i = 0;
+
+        // System Real-Time Messages leave the running status untouched, System Common Messages cancel it
+        let data3: [u8; 4] = [0b11111000, 0b11110110, 0b00110100, 0b00000000];
+        if parse_midi_event_with_running_status_at(&data3, &mut i, &mut running_status).is_err() || running_status != Some(0b10010011) {
+            panic!("3. fail: running status changed by a System Real-Time Message");
+        }
+        if parse_midi_event_with_running_status_at(&data3, &mut i, &mut running_status).is_err() || running_status.is_some() {
+            panic!("3. fail: running status not cancelled by a System Common Message");
+        }
+        if parse_midi_event_with_running_status_at(&data3, &mut i, &mut running_status).is_ok() {
+            panic!("3. fail: data byte accepted without a running status");
+        }
+    }
+
+    #[test]
+    fn test_system_exclusive() {
+        // F0 len data
+
+        let mut i: usize = 0;
+
+        let data1: [u8; 8] = [0xF0, 0x06, 0x43, 0x12, 0x00, 0x07, 0x01, 0xF7];
+        let result1 = match parse_midi_event_at(&data1, &mut i) {
+            Ok(r) => r,
+            Err(e) => panic!("{e}")
+        };
+        if result1.manufacturer_id() != Some(0x43) {
+            panic!("1. fail: wrong manufacturer id");
+        }
+        if let midi_event::MidiEvent::SystemExclusive { data } = result1 {
+            if data != vec![0x43, 0x12, 0x00, 0x07, 0x01, 0xF7] || i != 8 {
+                panic!("1. fail: wrong parameters");
+            }
+        } else {
+            panic!("1. fail: wrong enum variant");
+        }
+        i = 0;
+
+        // The length is a variable-length quantity
+        let mut data2 = vec![0xF0, 0x81, 0x00];
+        data2.extend([0x11; 128]);
+        let result2 = match parse_midi_event_at(&data2, &mut i) {
+            Ok(r) => r,
+            Err(e) => panic!("{e}")
+        };
+        if let midi_event::MidiEvent::SystemExclusive { data } = result2 {
+            if data.len() != 128 || i != 131 {
+                panic!("2. fail: wrong parameters");
+            }
+        } else {
+            panic!("2. fail: wrong enum variant");
+        }
+        i = 0;
+
+        let data3: [u8; 4] = [0xF0, 0x05, 0x43, 0xF7];
+        if parse_midi_event_at(&data3, &mut i).is_ok() {
+            panic!("3. fail: truncated data accepted");
+        }
+    }
+
+    #[test]
+    fn test_system_exclusive_escape() {
+        // F7 len data
+
+        let mut i: usize = 0;
+
+        let data1: [u8; 4] = [0xF7, 0x02, 0xF3, 0x01];
+        let result1 = match parse_midi_event_at(&data1, &mut i) {
+            Ok(r) => r,
+            Err(e) => panic!("{e}")
+        };
+        if let midi_event::MidiEvent::SystemExclusiveEscape { data } = result1 {
+            if data != vec![0xF3, 0x01] || i != 4 {
+                panic!("1. fail: wrong parameters");
+            }
+        } else {
+            panic!("1. fail: wrong enum variant");
+        }
+    }
+
+    #[test]
+    fn test_divided_system_exclusive() {
+        // F0 03 43 12 00, F7 02 07 01, F8, F7 01 F7, F7 01 F8
+
+        let mut i: usize = 0;
+
+        let data1: [u8; 16] = [0xF0, 0x03, 0x43, 0x12, 0x00, 0xF7, 0x02, 0x07, 0x01, 0xF8, 0xF7, 0x01, 0xF7, 0xF7, 0x01, 0xF8];
+        let mut events = Vec::<midi_event::MidiEvent>::new();
+        while i < data1.len() {
+            match parse_midi_event_at(&data1, &mut i) {
+                Ok(r) => events.push(r),
+                Err(e) => panic!("{e}")
+            };
+        }
+
+        let messages = midi_event::reassemble_system_exclusive(&events);
+        if messages != vec![vec![0xF0, 0x43, 0x12, 0x00, 0x07, 0x01, 0xF7]] {
+            panic!("1. fail: wrong messages ({:X?})", messages);
+        }
+    }
+
+    #[test]
+    fn test_undefined_event_codes() {
+        // F1, F4, F5, F9 and FD are not defined
+
+        for code in [0b11110001, 0b11110100, 0b11110101, 0b11111001, 0b11111101] {
+            let mut i: usize = 0;
+
+            let data1: [u8; 3] = [code, 0x00, 0x00];
+            if parse_midi_event_at(&data1, &mut i).is_ok() {
+                panic!("1. fail: undefined event code {:X} accepted", code);
+            }
+        }
+
+        let mut i: usize = 0;
+
+        let data2: [u8; 0] = [];
+        if parse_midi_event_at(&data2, &mut i).is_ok() {
+            panic!("2. fail: empty data accepted");
+        }
+    }
+
     // Meta-Events
 
     #[test]
@@ -1123,6 +1241,17 @@ mod test {
         if res3.is_some() || i != 0 {
             panic!("test3 returned Some");
         }
+
+        // Shift-JIS, kept as it was stored
+        let data4 = [0xFF, 0x03, 0x04, 0x93, 0x8C, 0x95, 0xFB];
+        let res4 = try_parse_meta_event(&data4, &mut i);
+        if let Some(meta_event::MetaEvent::TrackName { name }) = res4 {
+            if name.as_bytes() != &data4[3..] || name.decode(super::super::text::TextEncoding::ShiftJis).as_deref() != Some("東方") {
+                panic!("test4 returned wrong data");
+            }
+        } else {
+            panic!("test4 failed");
+        }
     }
 
     #[test]
@@ -1391,8 +1520,8 @@ mod test {
 
         let data1 = [0xFF, 0x58, 0x04, 0x02, 0x02, 0x09, 0x09];
         let res1 = try_parse_meta_event(&data1, &mut i);
-        if let Some(meta_event::MetaEvent::TimeSignature { numerator, denominator, midi_clocks_per_metronome_click, thirty_second_notes_per_midi_quarter_note }) = res1 {
-            if numerator != 2 || denominator != 2 || midi_clocks_per_metronome_click != 9 || thirty_second_notes_per_midi_quarter_note != 9 {
+        if let Some(meta_event::MetaEvent::TimeSignature { signature }) = res1 {
+            if signature.to_raw() != (2, 2, 9, 9) || signature.denominator() != 4 {
                 panic!("test1 returned wrong data");
             }
         } else {
@@ -1402,8 +1531,8 @@ mod test {
 
         let data2 = [0xFF, 0x58, 0x04, 0x02, 0x03, 0x04, 0x05];
         let res2 = try_parse_meta_event(&data2, &mut i);
-        if let Some(meta_event::MetaEvent::TimeSignature { numerator, denominator, midi_clocks_per_metronome_click, thirty_second_notes_per_midi_quarter_note }) = res2 {
-            if numerator != 2 || denominator != 3 || midi_clocks_per_metronome_click != 4 || thirty_second_notes_per_midi_quarter_note != 5 {
+        if let Some(meta_event::MetaEvent::TimeSignature { signature }) = res2 {
+            if signature.to_raw() != (2, 3, 4, 5) || signature.denominator() != 8 {
                 panic!("test2 returned wrong data");
             }
         } else {
@@ -1416,6 +1545,20 @@ mod test {
         if res3.is_some() || i != 0 {
             panic!("test3 returned Some");
         }
+
+        // A numerator of 0, kept as it is
+        let data4 = [0xFF, 0x58, 0x04, 0x00, 0x02, 0x18, 0x08];
+        match parse_meta_event_at(&data4, &mut i) {
+            Ok(e) => if e != (meta_event::MetaEvent::InvalidTimeSignature {
+                numerator: 0,
+                denominator_exponent: 2,
+                midi_clocks_per_metronome_click: 0x18,
+                thirty_second_notes_per_midi_quarter_note: 0x08
+            }) || i != 7 {
+                panic!("test4 returned wrong data {e}");
+            },
+            Err(e) => panic!("test4 failed {e}")
+        }
     }
 
     #[test]
@@ -1425,8 +1568,8 @@ mod test {
 
         let data1 = [0xFF, 0x59, 0x02, 0x02, 0x01];
         let res1 = try_parse_meta_event(&data1, &mut i);
-        if let Some(meta_event::MetaEvent::KeySignature { sf, mi }) = res1 {
-            if sf != 0x02 || !mi {
+        if let Some(meta_event::MetaEvent::KeySignature { key }) = res1 {
+            if key.accidentals() != 2 || !key.is_minor() {
                 panic!("test1 returned wrong data");
             }
         } else {
@@ -1436,8 +1579,8 @@ mod test {
 
         let data2 = [0xFF, 0x59, 0x02, 0x05, 0x00];
         let res2 = try_parse_meta_event(&data2, &mut i);
-        if let Some(meta_event::MetaEvent::KeySignature { sf, mi }) = res2 {
-            if sf != 0x05 || mi {
+        if let Some(meta_event::MetaEvent::KeySignature { key }) = res2 {
+            if key.accidentals() != 5 || key.is_minor() {
                 panic!("test2 returned wrong data");
             }
         } else {
@@ -1450,6 +1593,44 @@ mod test {
         if res3.is_some() || i != 0 {
             panic!("test3 returned Some");
         }
+
+        // E♭ minor, 6 flats
+        let data4 = [0xFF, 0x59, 0x02, 0xFA, 0x01];
+        match parse_meta_event_at(&data4, &mut i) {
+            Ok(meta_event::MetaEvent::KeySignature { key }) => if key.accidentals() != -6 || key.to_string() != "E♭ minor" {
+                panic!("test4 returned wrong data");
+            },
+            _ => panic!("test4 failed")
+        }
+        i = 0;
+
+        // 8 sharps, then a mode of 2, kept as they are
+        for (data, sf, mi) in [([0xFF, 0x59, 0x02, 0x08, 0x00], 8, 0), ([0xFF, 0x59, 0x02, 0x00, 0x02], 0, 2)] {
+            match parse_meta_event_at(&data, &mut i) {
+                Ok(e) => if e != (meta_event::MetaEvent::InvalidKeySignature { sf, mi }) || i != 5 {
+                    panic!("test5 returned wrong data {e}");
+                },
+                Err(e) => panic!("test5 failed {e}")
+            }
+            i = 0;
+        }
+    }
+
+    #[test]
+    fn test_meta_event_invalid_length() {
+        // FF 51 02 tt tt - SetTempo must be 3 bytes long
+        let mut i = 0;
+
+        let data1 = [0xFF, 0x51, 0x02, 0x07, 0xA1];
+        if parse_meta_event_at(&data1, &mut i).is_ok() {
+            panic!("test1 returned Ok");
+        }
+        i = 0;
+
+        let data2 = [0x90, 0x51, 0x02];
+        if parse_meta_event_at(&data2, &mut i).is_ok() {
+            panic!("test2 returned Ok");
+        }
     }
 
     #[test]
@@ -0,0 +1,326 @@
+use super::error::*;
+use super::util::*;
+use super::{chunk, borrowed, options};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkKind {
+    Header,
+    Track,
+    Unknown([u8; 4])
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkEntry {
+    pub kind: ChunkKind,
+    // The position of the chunk type
+    pub offset: usize,
+    // The position of the chunk data, 8 bytes after the offset
+    pub data_offset: usize,
+    // The length as written in the file, the data can be cut short at the end of the file
+    pub length: usize
+}
+
+// The positions of the chunks in a file, read without parsing any track.
+// The tracks can then be parsed one by one when needed, or all of them in parallel.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkIndex<'a> {
+    data: &'a [u8],
+    chunks: Vec<ChunkEntry>
+}
+
+impl<'a> ChunkIndex<'a> {
+    pub fn new(data: &'a [u8]) -> Result<Self, ParsingError> {
+        let mut i: usize = 0;
+        let mut chunks = Vec::<ChunkEntry>::new();
+
+        while i < data.len() {
+            let offset = i;
+
+            let chunk_type_raw = match read_bytes_at(data, &mut i, 4) {
+                Ok(t) => t,
+                Err(e) => return Err(ParsingError::eof("MTrk.chunk_type", e))
+            };
+            let chunk_length_raw = match read_bytes_at(data, &mut i, 4) {
+                Ok(l) => l,
+                Err(e) => return Err(ParsingError::eof("MTrk.length", e))
+            };
+            let length = u32::from_be_bytes(chunk_length_raw.try_into().unwrap()) as usize;
+
+            let kind = match chunk_type_raw {
+                b"MThd" => ChunkKind::Header,
+                b"MTrk" => ChunkKind::Track,
+                id => ChunkKind::Unknown(id.try_into().unwrap())
+            };
+
+            chunks.push(ChunkEntry { kind, offset, data_offset: i, length });
+            i = i.saturating_add(length);
+        }
+
+        Ok(Self { data, chunks })
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn chunks(&self) -> &[ChunkEntry] {
+        &self.chunks
+    }
+
+    pub fn tracks(&self) -> impl Iterator<Item = &ChunkEntry> {
+        self.chunks.iter().filter(|c| c.kind == ChunkKind::Track)
+    }
+
+    pub fn number_of_tracks(&self) -> usize {
+        self.tracks().count()
+    }
+
+    fn header_entry(&self) -> Result<&ChunkEntry, ParsingError> {
+        let mut headers = self.chunks.iter().filter(|c| c.kind == ChunkKind::Header);
+
+        let entry = match headers.next() {
+            Some(e) => e,
+            None => return Err(ParsingError::new(ParsingErrorKind::MissingHeader, 0))
+        };
+        if let Some(duplicate) = headers.next() {
+            return Err(ParsingError::new(ParsingErrorKind::DuplicateHeader, duplicate.offset));
+        }
+
+        Ok(entry)
+    }
+
+    // The MThd chunk, the file has to have exactly one
+    pub fn header(&self) -> Result<chunk::Header, ParsingError> {
+        let entry = self.header_entry()?;
+
+        // Longer headers are allowed, the bytes after the known fields are ignored
+        if entry.length < chunk::MTHD_LENGTH {
+            return Err(ParsingError::new(ParsingErrorKind::InvalidChunkLength {
+                chunk_type: *b"MThd",
+                expected: chunk::MTHD_LENGTH,
+                found: entry.length
+            }, entry.offset + 4));
+        }
+
+        let mut i = entry.data_offset;
+        let header = super::parse_header_at(self.data, &mut i)?;
+
+        if self.chunk_data(entry).is_none() {
+            return Err(ParsingError::eof("MThd", EOFError {
+                position: i,
+                tried_to_read: entry.length - chunk::MTHD_LENGTH,
+                buffer_size: self.data.len()
+            }));
+        }
+
+        Ok(header)
+    }
+
+    // The data of an unknown chunk, None if it's cut short by the end of the file
+    pub fn chunk_data(&self, entry: &ChunkEntry) -> Option<&'a [u8]> {
+        self.data.get(entry.data_offset..entry.data_offset.checked_add(entry.length)?)
+    }
+
+    pub fn parse_track(&self, track_index: usize) -> Option<Result<chunk::Track, ParsingError>> {
+        let entry = self.tracks().nth(track_index)?;
+        let mut i = entry.data_offset;
+
+        Some(super::parse_track_at(self.data, &mut i, entry.length, false).map_err(|e| e.at_track(track_index)))
+    }
+
+    // The events of a track borrowed from the data, parsed as they are iterated.
+    pub fn track_events(&self, track_index: usize) -> Option<borrowed::TrackEvents<'a>> {
+        let entry = self.tracks().nth(track_index)?;
+        Some(borrowed::TrackEvents::new(self.data, entry.data_offset, entry.length))
+    }
+
+    // Parses all of the tracks, spread over the available threads.
+    // On failure the error of the first track that failed is returned.
+    pub fn parse_tracks_parallel(&self) -> Result<Vec<chunk::Track>, ParsingError> {
+        let entries: Vec<(usize, &ChunkEntry)> = self.tracks().enumerate().collect();
+        if entries.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let threads = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1).min(entries.len());
+        let per_thread = entries.len().div_ceil(threads);
+
+        let results: Vec<Result<chunk::Track, ParsingError>> = std::thread::scope(|scope| {
+            let handles: Vec<_> = entries.chunks(per_thread).map(|group| (group, scope.spawn(move || {
+                group.iter().map(|(track_index, entry)| {
+                    let mut i = entry.data_offset;
+                    super::parse_track_at(self.data, &mut i, entry.length, false).map_err(|e| e.at_track(*track_index))
+                }).collect::<Vec<_>>()
+            }))).collect();
+
+            handles.into_iter().flat_map(|(group, h)| match h.join() {
+                Ok(tracks) => tracks,
+                // The tracks of a thread that panicked fail instead of panicking again
+                Err(_) => group.iter().map(|(track_index, entry)| {
+                    Err(ParsingError::new(ParsingErrorKind::ThreadPanicked, entry.data_offset).at_track(*track_index))
+                }).collect()
+            }).collect()
+        });
+
+        results.into_iter().collect()
+    }
+
+    // The unknown chunks, failing on one cut short by the end of the file
+    pub fn unknown_chunks(&self) -> Result<Vec<chunk::UnknownChunk>, ParsingError> {
+        self.chunks.iter().filter_map(|entry| match entry.kind {
+            ChunkKind::Unknown(id) => Some(match self.chunk_data(entry) {
+                Some(data) => Ok(chunk::UnknownChunk { id, data: data.to_vec() }),
+                None => Err(ParsingError::eof("UnknownChunk.data", EOFError {
+                    position: entry.data_offset,
+                    tried_to_read: entry.length,
+                    buffer_size: self.data.len()
+                }))
+            }),
+            _ => None
+        }).collect()
+    }
+
+    // Parses the file like `parse_midi_file_with_options` in strict mode, with the same warnings
+    // about the position of the header and the number of tracks.
+    pub fn parse_midi_file_with_warnings(&self) -> Result<(chunk::MidiFile, Vec<options::ParseWarning>), ParsingError> {
+        let header = self.header()?;
+        let header_position = self.header_entry()?.offset;
+
+        let midi_file = chunk::MidiFile {
+            header,
+            tracks: self.parse_tracks_parallel()?,
+            unknown_chunks: self.unknown_chunks()?,
+            encoding: None
+        };
+
+        let mut warnings = Vec::<options::ParseWarning>::new();
+        if header_position != 0 {
+            warnings.push(options::ParseWarning::new(options::ParseWarningKind::HeaderNotFirst, header_position));
+        }
+        warnings.extend(super::validate_track_count(&midi_file.header, midi_file.tracks.len(), header_position));
+
+        Ok((midi_file, warnings))
+    }
+
+    pub fn parse_midi_file(&self) -> Result<chunk::MidiFile, ParsingError> {
+        self.parse_midi_file_with_warnings().map(|(f, _)| f)
+    }
+}
+
+pub fn index_midi_file(data: &[u8]) -> Result<ChunkIndex<'_>, ParsingError> {
+    ChunkIndex::new(data)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_index_test_midis() {
+        for (name, data) in crate::parser::read_test_midis() {
+            let midi_file = match super::super::parse_midi_file(&data) {
+                Ok(f) => f,
+                Err(e) => panic!("{name}: {e}")
+            };
+
+            let index = match index_midi_file(&data) {
+                Ok(i) => i,
+                Err(e) => panic!("{name}: {e}")
+            };
+            if index.number_of_tracks() != midi_file.tracks.len() || index.header() != Ok(midi_file.header) {
+                panic!("{name}: wrong index");
+            }
+
+            // The last track alone
+            let last = midi_file.tracks.len() - 1;
+            if index.parse_track(last) != Some(Ok(midi_file.tracks[last].clone())) || index.parse_track(last + 1).is_some() {
+                panic!("{name}: wrong track");
+            }
+            let events: Result<Vec<chunk::TrackEvent>, ParsingError> = index.track_events(last).unwrap().map(|e| e.map(chunk::TrackEvent::from)).collect();
+            if events.map(chunk::Track::new) != Ok(midi_file.tracks[last].clone()) {
+                panic!("{name}: wrong borrowed track");
+            }
+
+            if index.parse_midi_file() != Ok(midi_file) {
+                panic!("{name}: wrong parallel parsing");
+            }
+        }
+    }
+
+    #[test]
+    fn test_index_chunks() {
+        let data: Vec<u8> = vec![
+            b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 1, 0, 2, 0, 0x60,
+            b'M', b'T', b'r', b'k', 0, 0, 0, 4, 0x00, 0xFF, 0x2F, 0x00,
+            b'X', b'Y', b'Z', b'W', 0, 0, 0, 3, 1, 2, 3,
+            b'M', b'T', b'r', b'k', 0, 0, 0, 4, 0x00, 0xF4, 0x2F, 0x00
+        ];
+
+        let index = match index_midi_file(&data) {
+            Ok(i) => i,
+            Err(e) => panic!("{e}")
+        };
+        let kinds: Vec<(ChunkKind, usize)> = index.chunks().iter().map(|c| (c.kind, c.offset)).collect();
+        if kinds != vec![(ChunkKind::Header, 0), (ChunkKind::Track, 14), (ChunkKind::Unknown(*b"XYZW"), 26), (ChunkKind::Track, 37)] {
+            panic!("1. fail: wrong chunks {:?}", kinds);
+        }
+        if index.chunk_data(&index.chunks()[2]) != Some(&[1, 2, 3][..])
+            || index.unknown_chunks() != Ok(vec![chunk::UnknownChunk { id: *b"XYZW", data: vec![1, 2, 3] }]) {
+            panic!("1. fail: wrong chunk data");
+        }
+
+        // Only the broken track fails
+        if !matches!(index.parse_track(0), Some(Ok(_))) {
+            panic!("2. fail: first track");
+        }
+        match index.parse_midi_file() {
+            Err(e) => if e.kind != ParsingErrorKind::UnknownStatusByte(0xF4) || e.track_index != Some(1) {
+                panic!("2. fail: wrong error {e}");
+            },
+            Ok(_) => panic!("2. fail: no error")
+        }
+
+        // A chunk header cut short
+        if index_midi_file(&data[..40]).is_ok() {
+            panic!("3. fail: truncated chunk header");
+        }
+    }
+
+    #[test]
+    fn test_index_header() {
+        let header: [u8; 14] = [b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 1, 0, 2, 0, 0x60];
+        let track: [u8; 12] = [b'M', b'T', b'r', b'k', 0, 0, 0, 4, 0x00, 0xFF, 0x2F, 0x00];
+
+        // The same errors as the whole-file parser
+        for (data, expected) in [
+            ([&track[..], &track[..]].concat(), ParsingErrorKind::MissingHeader),
+            ([&header[..], &track[..], &header[..]].concat(), ParsingErrorKind::DuplicateHeader)
+        ] {
+            let index = match index_midi_file(&data) {
+                Ok(i) => i,
+                Err(e) => panic!("{e}")
+            };
+            match index.parse_midi_file() {
+                Err(e) => if e.kind != expected || Err(e.clone()) != super::super::parse_midi_file(&data) || index.header() != Err(e) {
+                    panic!("1. fail: wrong error, expected {:?}", expected);
+                },
+                Ok(_) => panic!("1. fail: no error, expected {:?}", expected)
+            }
+        }
+
+        // The header declares 2 tracks
+        let data = [&header[..], &track[..]].concat();
+        let (midi_file, warnings) = match index_midi_file(&data).and_then(|i| i.parse_midi_file_with_warnings()) {
+            Ok(r) => r,
+            Err(e) => panic!("{e}")
+        };
+        if midi_file.tracks.len() != 1 || warnings.iter().map(|w| &w.kind).ne([&options::ParseWarningKind::TrackCountMismatch { expected: 2, found: 1 }]) {
+            panic!("2. fail: wrong warnings {:?}", warnings);
+        }
+        if super::super::parse_midi_file_with_options(&data, &options::ParseOptions::strict()) != Ok((midi_file, warnings)) {
+            panic!("2. fail: different from the whole-file parser");
+        }
+    }
+
+}
@@ -1,2 +1,297 @@
-// #[derive(Debug)]
-// pub 
\ No newline at end of file
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Mode {
+    Major,
+    Minor
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum NoteLetter {
+    C,
+    D,
+    E,
+    F,
+    G,
+    A,
+    B
+}
+
+impl NoteLetter {
+    const ALL: [Self; 7] = [Self::C, Self::D, Self::E, Self::F, Self::G, Self::A, Self::B];
+
+    // The pitch class of the natural note, C = 0
+    pub fn pitch_class(self) -> u8 {
+        match self {
+            Self::C => 0,
+            Self::D => 2,
+            Self::E => 4,
+            Self::F => 5,
+            Self::G => 7,
+            Self::A => 9,
+            Self::B => 11
+        }
+    }
+
+    fn offset(self, steps: usize) -> Self {
+        Self::ALL[(self as usize + steps) % 7]
+    }
+}
+
+impl std::fmt::Display for NoteLetter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Accidental {
+    Flat,
+    Natural,
+    Sharp
+}
+
+impl Accidental {
+    pub fn semitones(self) -> i8 {
+        match self {
+            Self::Flat => -1,
+            Self::Natural => 0,
+            Self::Sharp => 1
+        }
+    }
+}
+
+impl std::fmt::Display for Accidental {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Flat => write!(f, "♭"),
+            Self::Natural => Ok(()),
+            Self::Sharp => write!(f, "♯")
+        }
+    }
+}
+
+// A note name without an octave, e.g. E♭
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SpelledNote {
+    pub letter: NoteLetter,
+    pub accidental: Accidental
+}
+
+impl SpelledNote {
+    pub fn pitch_class(self) -> u8 {
+        (self.letter.pitch_class() as i8 + self.accidental.semitones()).rem_euclid(12) as u8
+    }
+}
+
+impl std::fmt::Display for SpelledNote {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.letter, self.accidental)
+    }
+}
+
+// The order in which sharps are added to a key signature, flats are added in the reverse order
+const ORDER_OF_SHARPS: [NoteLetter; 7] = [NoteLetter::F, NoteLetter::C, NoteLetter::G, NoteLetter::D, NoteLetter::A, NoteLetter::E, NoteLetter::B];
+
+pub const MAX_ACCIDENTALS: i8 = 7;
+
+// A key signature as stored in the FF 59 meta-event: the number of sharps (positive) or flats (negative) and the mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct KeySignature {
+    accidentals: i8,
+    mode: Mode
+}
+
+impl KeySignature {
+    pub const C_MAJOR: Self = Self { accidentals: 0, mode: Mode::Major };
+
+    // `None` if there are more than 7 sharps or flats
+    pub fn new(accidentals: i8, mode: Mode) -> Option<Self> {
+        if !(-MAX_ACCIDENTALS..=MAX_ACCIDENTALS).contains(&accidentals) {
+            return None;
+        }
+
+        Some(Self { accidentals, mode })
+    }
+
+    // From the two data bytes of the meta-event, `None` if sf is out of range or mi isn't 0 or 1
+    pub fn from_raw(sf: u8, mi: u8) -> Option<Self> {
+        let mode = match mi {
+            0 => Mode::Major,
+            1 => Mode::Minor,
+            _ => return None
+        };
+
+        Self::new(sf as i8, mode)
+    }
+
+    // (sf, mi)
+    pub fn to_raw(self) -> (u8, u8) {
+        (self.accidentals as u8, (self.mode == Mode::Minor) as u8)
+    }
+
+    // Sharps are positive, flats negative
+    pub fn accidentals(self) -> i8 {
+        self.accidentals
+    }
+
+    pub fn sharps(self) -> u8 {
+        self.accidentals.max(0) as u8
+    }
+
+    pub fn flats(self) -> u8 {
+        (-self.accidentals).max(0) as u8
+    }
+
+    pub fn mode(self) -> Mode {
+        self.mode
+    }
+
+    pub fn is_minor(self) -> bool {
+        self.mode == Mode::Minor
+    }
+
+    // The key with the same accidentals in the other mode, e.g. C minor for E♭ major
+    pub fn relative(self) -> Self {
+        let mode = match self.mode {
+            Mode::Major => Mode::Minor,
+            Mode::Minor => Mode::Major
+        };
+
+        Self { accidentals: self.accidentals, mode }
+    }
+
+    // The letters that are sharped or flatted, in the order they are written on the staff
+    pub fn altered_letters(self) -> Vec<NoteLetter> {
+        if self.accidentals >= 0 {
+            ORDER_OF_SHARPS[..self.accidentals as usize].to_vec()
+        } else {
+            ORDER_OF_SHARPS.iter().rev().take(self.flats() as usize).copied().collect()
+        }
+    }
+
+    pub fn accidental_of(self, letter: NoteLetter) -> Accidental {
+        if !self.altered_letters().contains(&letter) {
+            Accidental::Natural
+        } else if self.accidentals > 0 {
+            Accidental::Sharp
+        } else {
+            Accidental::Flat
+        }
+    }
+
+    pub fn tonic(self) -> SpelledNote {
+        // Every sharp moves the major tonic a fifth up (4 letters), every flat a fifth down
+        let major_letter = NoteLetter::C.offset((self.accidentals as i32 * 4).rem_euclid(7) as usize);
+        let letter = match self.mode {
+            Mode::Major => major_letter,
+            // The relative minor is a sixth above
+            Mode::Minor => major_letter.offset(5)
+        };
+
+        SpelledNote { letter, accidental: self.accidental_of(letter) }
+    }
+
+    // C = 0
+    pub fn tonic_pitch_class(self) -> u8 {
+        self.tonic().pitch_class()
+    }
+
+    // The seven notes of the scale from the tonic, the natural minor scale for minor keys
+    pub fn scale(self) -> [SpelledNote; 7] {
+        let tonic = self.tonic().letter;
+
+        std::array::from_fn(|step| {
+            let letter = tonic.offset(step);
+            SpelledNote { letter, accidental: self.accidental_of(letter) }
+        })
+    }
+}
+
+impl Default for KeySignature {
+    fn default() -> Self {
+        Self::C_MAJOR
+    }
+}
+
+impl std::fmt::Display for KeySignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.mode {
+            Mode::Major => write!(f, "{} major", self.tonic()),
+            Mode::Minor => write!(f, "{} minor", self.tonic())
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_key_names() {
+        let major: Vec<String> = (-7..=7).map(|a| KeySignature::new(a, Mode::Major).unwrap().to_string()).collect();
+        if major != ["C♭", "G♭", "D♭", "A♭", "E♭", "B♭", "F", "C", "G", "D", "A", "E", "B", "F♯", "C♯"].map(|t| format!("{t} major")) {
+            panic!("1. fail: wrong major keys {:?}", major);
+        }
+
+        let minor: Vec<String> = (-7..=7).map(|a| KeySignature::new(a, Mode::Minor).unwrap().to_string()).collect();
+        if minor != ["A♭", "E♭", "B♭", "F", "C", "G", "D", "A", "E", "B", "F♯", "C♯", "G♯", "D♯", "A♯"].map(|t| format!("{t} minor")) {
+            panic!("2. fail: wrong minor keys {:?}", minor);
+        }
+
+        // E♭ minor, 6 flats
+        let key = KeySignature::from_raw(0xFA, 1).unwrap();
+        if key.to_string() != "E♭ minor" || key.tonic_pitch_class() != 3 || key.flats() != 6 || key.relative().to_string() != "G♭ major" {
+            panic!("3. fail: wrong E♭ minor");
+        }
+        if KeySignature::from_raw(0xF9, 0).unwrap().tonic_pitch_class() != 11 || KeySignature::from_raw(7, 1).unwrap().tonic_pitch_class() != 10 {
+            panic!("3. fail: wrong pitch classes");
+        }
+    }
+
+    #[test]
+    fn test_scale_spelling() {
+        // D major: F♯ and C♯
+        let key = KeySignature::new(2, Mode::Major).unwrap();
+        if key.altered_letters() != vec![NoteLetter::F, NoteLetter::C] || key.accidental_of(NoteLetter::F) != Accidental::Sharp || key.accidental_of(NoteLetter::G) != Accidental::Natural {
+            panic!("1. fail: wrong accidentals");
+        }
+        let scale: Vec<String> = key.scale().iter().map(|n| n.to_string()).collect();
+        if scale != ["D", "E", "F♯", "G", "A", "B", "C♯"] {
+            panic!("1. fail: wrong scale {:?}", scale);
+        }
+
+        // F minor: B♭, E♭, A♭, D♭
+        let key = KeySignature::new(-4, Mode::Minor).unwrap();
+        if key.altered_letters() != vec![NoteLetter::B, NoteLetter::E, NoteLetter::A, NoteLetter::D] {
+            panic!("2. fail: wrong accidentals");
+        }
+        let pitch_classes: Vec<u8> = key.scale().iter().map(|n| n.pitch_class()).collect();
+        if pitch_classes != [5, 7, 8, 10, 0, 1, 3] {
+            panic!("2. fail: wrong scale {:?}", pitch_classes);
+        }
+
+        // C♭ major, every letter flatted
+        if KeySignature::new(-7, Mode::Major).unwrap().scale().iter().any(|n| n.accidental != Accidental::Flat) {
+            panic!("3. fail: wrong scale");
+        }
+    }
+
+    #[test]
+    fn test_validation() {
+        for (sf, mi) in [(8, 0), (0xF8, 0), (0x80, 1), (0, 2), (0, 0xFF)] {
+            if KeySignature::from_raw(sf, mi).is_some() {
+                panic!("1. fail: ({sf}, {mi}) accepted");
+            }
+        }
+        if KeySignature::new(-8, Mode::Major).is_some() || KeySignature::new(7, Mode::Minor).is_none() {
+            panic!("2. fail: wrong range");
+        }
+
+        for (sf, mi) in [(0, 0), (7, 1), (0xF9, 0), (0xFD, 1)] {
+            if KeySignature::from_raw(sf, mi).map(KeySignature::to_raw) != Some((sf, mi)) {
+                panic!("3. fail: ({sf}, {mi}) changed");
+            }
+        }
+    }
+
+}
@@ -1,28 +1,32 @@
-#[derive(Debug)]
+use super::text::Text;
+use super::key_signature::KeySignature;
+use super::time_signature::TimeSignature;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum MetaEvent {
     SequenceNumber {
         number: u16
     },
     TextEvent {
-        text: String
+        text: Text
     },
     CopyrightNotice {
-        notice: String
+        notice: Text
     },
     TrackName {
-        name: String
+        name: Text
     },
     InstrumentName {
-        name: String
+        name: Text
     },
     Lyric {
-        text: String
+        text: Text
     },
     Marker {
-        name: String
+        name: Text
     },
     CuePoint {
-        text: String
+        text: Text
     },
     MIDIChannelPrefix {
         channel: u8
@@ -31,22 +35,34 @@ pub enum MetaEvent {
     SetTempo {
         microseconds_per_midi_quarter_note: u64
     },
+    // The timecode at which the track starts, see `SMPTETimecode::from_raw`.
+    // The hour byte also holds the frame rate, the fractional frames are hundredths of a frame.
     SMPTEOffset {
         hour: u8,
         minute: u8,
         second: u8,
-        frame: u8,              // don't exactly know what these 2 do, something SMPTE-related :)
+        frame: u8,
         fractional_frames: u8
     },
     TimeSignature {
+        signature: TimeSignature
+    },
+    // A time signature with a numerator of 0 or a denominator too large for a u32, kept as it was stored.
+    // `TimeSignature::from_raw` rejects these bytes.
+    InvalidTimeSignature {
         numerator: u8,
-        denominator: u8,
+        denominator_exponent: u8,
         midi_clocks_per_metronome_click: u8,
         thirty_second_notes_per_midi_quarter_note: u8
     },
     KeySignature {
+        key: KeySignature
+    },
+    // A key signature with more than 7 sharps or flats or a mode other than 0 or 1, kept as it was stored.
+    // `KeySignature::from_raw` rejects these bytes.
+    InvalidKeySignature {
         sf: u8,
-        mi: bool
+        mi: u8
     },
     SequencerSpecific {
         data: Vec<u8>
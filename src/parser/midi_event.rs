@@ -1,5 +1,5 @@
 #[allow(dead_code)]
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum MidiEvent {
     // Channel Voice Messages
     NoteOff {
@@ -60,8 +60,12 @@ pub enum MidiEvent {
     },
 
     // System Common Messages
+    // F0 <length> <bytes>, the bytes are kept as stored in the file, including the terminating F7 if present.
     SystemExclusive {
-        manufacturer_id: u8,
+        data: Vec<u8>
+    },
+    // F7 <length> <bytes>, either a continuation packet of a divided System Exclusive message or an escape sequence.
+    SystemExclusiveEscape {
         data: Vec<u8>
     },
     SongPositionPointer {
@@ -81,6 +85,82 @@ pub enum MidiEvent {
     Reset
 }
 
+impl MidiEvent {
+    // The channel of a Channel Voice or Channel Mode message
+    pub fn channel(&self) -> Option<u8> {
+        match self {
+            Self::NoteOff { channel, .. }
+            | Self::NoteOn { channel, .. }
+            | Self::PolyphonicKeyPressure { channel, .. }
+            | Self::ControlChange { channel, .. }
+            | Self::ProgramChange { channel, .. }
+            | Self::ChannelPressure { channel, .. }
+            | Self::PitchWheelChange { channel, .. }
+            | Self::LocalControlOff { channel }
+            | Self::LocalControlOn { channel }
+            | Self::AllNotesOff { channel }
+            | Self::OmniModeOff { channel }
+            | Self::OmniModeOn { channel }
+            | Self::MonoModeOn { channel, .. }
+            | Self::PolyModeOn { channel } => Some(*channel),
+            _ => None
+        }
+    }
+
+    pub fn manufacturer_id(&self) -> Option<u8> {
+        match self {
+            Self::SystemExclusive { data } => data.first().copied(),
+            _ => None
+        }
+    }
+}
+
+// Joins divided System Exclusive messages (an F0 packet followed by F7 continuation packets) into complete messages.
+// Every message starts with F0 and ends with F7, except for an unterminated message at the end, which is returned as-is.
+// F7 packets outside of a divided message are escape sequences and are not part of any message.
+pub fn reassemble_system_exclusive<'a, I: IntoIterator<Item = &'a MidiEvent>>(events: I) -> Vec<Vec<u8>> {
+    let mut messages = Vec::<Vec<u8>>::new();
+    let mut pending: Option<Vec<u8>> = None;
+
+    for event in events {
+        match event {
+            MidiEvent::SystemExclusive { data } => {
+                // A new message while another one is pending, the pending one is never going to be terminated
+                if let Some(message) = pending.take() {
+                    messages.push(message);
+                }
+
+                let mut message = vec![0xF0];
+                message.extend_from_slice(data);
+
+                if data.last() == Some(&0xF7) {
+                    messages.push(message);
+                } else {
+                    pending = Some(message);
+                }
+            },
+            MidiEvent::SystemExclusiveEscape { data } => {
+                if let Some(mut message) = pending.take() {
+                    message.extend_from_slice(data);
+
+                    if data.last() == Some(&0xF7) {
+                        messages.push(message);
+                    } else {
+                        pending = Some(message);
+                    }
+                }
+            },
+            _ => {}
+        }
+    }
+
+    if let Some(message) = pending {
+        messages.push(message);
+    }
+
+    messages
+}
+
 impl std::fmt::Display for MidiEvent {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{:#?}", self)
@@ -1,167 +1,941 @@
+pub mod error;
 mod util;
 pub mod chunk;
 pub mod midi_event;
 pub mod meta_event;
 mod event_parser;
+pub mod stream;
+pub mod borrowed;
+pub mod index;
+pub mod options;
+pub mod text;
+pub mod key_signature;
+pub mod time_signature;
 
+use error::*;
 use util::*;
-use event_parser::*;
 
-fn parse_header_at(data: &[u8], i: &mut usize) -> Result<chunk::Chunk, ParsingError> {
+pub(crate) use event_parser::parse_meta_event_at;
+
+fn parse_header_at(data: &[u8], i: &mut usize) -> Result<chunk::Header, ParsingError> {
     
     // Format
     let midi_file_format_raw = match read_bytes_at(data, i, 2) {
         Ok(f) => f,
-        Err(e) => return Err(ParsingError {
-            position: *i,
-            message: format!("Not enough data to read MThd.format\n{}", e)
-        })
+        Err(e) => return Err(ParsingError::eof("MThd.format", e))
     };
     let midi_file_format_idx = u16::from_be_bytes(midi_file_format_raw.try_into().unwrap());
     let midi_file_format = match midi_file_format_idx {
         0 => chunk::MidiFileFormat::SingleTrack,
         1 => chunk::MidiFileFormat::SimultaneousTracks,
         2 => chunk::MidiFileFormat::SequentialTracks,
-        _ => return Err(ParsingError {
-            position: *i,
-            message: format!("Undefined MIDI file format: {}\nThe file format can only be 0, 1 or 2", midi_file_format_idx)
-        })
+        _ => return Err(ParsingError::new(ParsingErrorKind::InvalidHeaderFormat(midi_file_format_idx), *i - 2))
     };
 
     // Number of tracks
     let number_of_tracks_raw = match read_bytes_at(data, i, 2) {
         Ok(n) => n,
-        Err(e) => return Err(ParsingError {
-            position: *i,
-            message: format!("Not enough data to read MThd.number_of_tracks\n{}", e)
-        })
+        Err(e) => return Err(ParsingError::eof("MThd.number_of_tracks", e))
     };
     let number_of_tracks = u16::from_be_bytes(number_of_tracks_raw.try_into().unwrap());
 
     // Number of tracks
     let division_raw = match read_bytes_at(data, i, 2) {
         Ok(d) => d,
-        Err(e) => return Err(ParsingError {
-            position: *i,
-            message: format!("Not enough data to read MThd.division\n{}", e)
-        })
+        Err(e) => return Err(ParsingError::eof("MThd.division", e))
     };
     let division_word = u16::from_be_bytes(division_raw.try_into().unwrap());
-    let division: chunk::Division;
-    if (division_word & (1 << 15)) == 0 {
-        division = chunk::Division::TicksPerQuarterNote(division_word);
+    let division = if (division_word & (1 << 15)) == 0 {
+        if division_word == 0 {
+            return Err(ParsingError::new(ParsingErrorKind::ZeroDivision, *i - 2));
+        }
+
+        chunk::Division::TicksPerQuarterNote(division_word)
     } else {
-        // division = chunk::Division::SMPTE(((division_word & 0xFF00) >> 8) as i8, (division_word & 0xFF) as u8);
-        unimplemented!();
-    }
+        // The upper byte is the negative frame rate, the lower byte the ticks per frame
+        let format_raw = (division_word >> 8) as u8 as i8;
+        let format = match chunk::SMPTEFormat::from_raw(format_raw) {
+            Some(f) => f,
+            None => return Err(ParsingError::new(ParsingErrorKind::InvalidSMPTEFormat(format_raw), *i - 2))
+        };
+        let ticks_per_frame = (division_word & 0xFF) as u8;
+        if ticks_per_frame == 0 {
+            return Err(ParsingError::new(ParsingErrorKind::ZeroDivision, *i - 2));
+        }
+
+        chunk::Division::SMPTE { format, ticks_per_frame }
+    };
 
-    Ok(chunk::Chunk::MThd {
+    Ok(chunk::Header {
         format: midi_file_format,
         number_of_tracks,
         division
     })
 }
 
-fn parse_track_event_at(data: &[u8], i: &mut usize) -> Result<chunk::TrackEvent, ParsingError> {
-    let delta_time = match parse_variable_length_at(data, i) {
-        Ok(dt) => dt,
-        Err(e) => panic!("Not enough data to read TrackEvent.delta_time\n{}", e)
-    };
-    
-    // Try parsing a meta-event
+// Recovers how the event that was just parsed had been stored, for the lossless parsing mode.
+fn read_event_encoding(data: &[u8], event_start: usize, status_start: usize, event_end: usize, event: &chunk::TrackEventType) -> chunk::EventEncoding {
+    let running_status = data[status_start] & 0b10000000 == 0;
+
+    let (data_length_size, original_data) = match event {
+        chunk::TrackEventType::Meta(meta_event) => {
+            // FF code length data
+            let mut data_start = status_start + 2;
+            let _ = parse_variable_length_at(data, &mut data_start);
+
+            let original = &data[data_start..event_end];
+            let reproduced = crate::writer::meta_event_data(meta_event).ok();
+            let original_data = if reproduced.as_deref() == Some(original) { None } else { Some(original.to_vec()) };
 
-    let meta_event_maybe = try_parse_meta_event(data, i);
+            (data_start - status_start - 2, original_data)
+        },
+        chunk::TrackEventType::Midi(midi_event::MidiEvent::SystemExclusive { .. } | midi_event::MidiEvent::SystemExclusiveEscape { .. }) => {
+            // F0/F7 length data
+            let mut data_start = status_start + 1;
+            let _ = parse_variable_length_at(data, &mut data_start);
 
-    if let Some(meta_event) = meta_event_maybe {
-        return Ok(chunk::TrackEvent {
-            delta_time,
-            event: chunk::TrackEventType::Meta(meta_event)
-        })
+            (data_start - status_start - 1, None)
+        },
+        _ => (0, None)
+    };
+
+    chunk::EventEncoding {
+        delta_time_size: (status_start - event_start) as u8,
+        running_status,
+        data_length_size: data_length_size as u8,
+        original_data
     }
+}
 
-    // Try parsing a midi-event
-    
-    match parse_midi_event_at(data, i) {
-        Ok(event) => Ok(chunk::TrackEvent {
-            delta_time,
-            event: chunk::TrackEventType::Midi(event)
-        }),
-        Err(e) => Err(e)
+fn parse_track_at(data: &[u8], i: &mut usize, length: usize, lossless: bool) -> Result<chunk::Track, ParsingError> {
+    parse_track_with(data, i, length, &options::ParseOptions { lossless, ..Default::default() }, &mut vec![])
+}
+
+// Events kept as they were stored although their values are out of range.
+fn invalid_event_warning(event: &borrowed::TrackEventType) -> Option<options::ParseWarningKind> {
+    match event {
+        borrowed::TrackEventType::Meta(borrowed::MetaEvent::InvalidTimeSignature { numerator, denominator_exponent, .. }) => {
+            Some(options::ParseWarningKind::InvalidTimeSignature { numerator: *numerator, denominator_exponent: *denominator_exponent })
+        },
+        borrowed::TrackEventType::Meta(borrowed::MetaEvent::InvalidKeySignature { sf, mi }) => Some(options::ParseWarningKind::InvalidKeySignature { sf: *sf as i8, mi: *mi }),
+        _ => None
     }
 }
 
-fn parse_track_at(data: &[u8], i: &mut usize, length: usize) -> Result<chunk::Chunk, ParsingError> {
+// The events are read and checked by `borrowed::TrackEvents`: they can't read past the declared length of the track,
+// and the track has to end with EndOfTrack.
+// In lenient mode the track stops at EndOfTrack or at the first error, which is returned as a warning.
+fn parse_track_with(data: &[u8], i: &mut usize, length: usize, options: &options::ParseOptions, warnings: &mut Vec<options::ParseWarning>) -> Result<chunk::Track, ParsingError> {
 
-    let i_at_chunk_data_start = *i;
+    let mut track_events = borrowed::TrackEvents::new(data, *i, length);
     let mut events = Vec::<chunk::TrackEvent>::new();
+    let mut end_of_track = false;
+
+    loop {
+        let event_start = track_events.position();
+        let result = track_events.next();
+        *i = track_events.position();
+
+        match result {
+            Some(Ok(event)) => {
+                end_of_track = event.event == borrowed::TrackEventType::Meta(borrowed::MetaEvent::EndOfTrack);
+                if let Some(kind) = invalid_event_warning(&event.event) {
+                    warnings.push(options::ParseWarning::new(kind, event_start));
+                }
+
+                let mut event: chunk::TrackEvent = event.into();
+                if options.lossless {
+                    // The delta-time was read successfully, it can't fail the second time
+                    let mut status_start = event_start;
+                    let _ = parse_variable_length_at(data, &mut status_start);
+
+                    event.encoding = Some(read_event_encoding(data, event_start, status_start, *i, &event.event));
+                }
+                events.push(event);
 
-    while *i < i_at_chunk_data_start + length {
-        let event_maybe = parse_track_event_at(data, i);
-        match event_maybe {
-            Ok(event) => events.push(event),
-            Err(e) => return Err(e)
+                if end_of_track && options.is_lenient() {
+                    break;
+                }
+            },
+            Some(Err(e)) if options.is_lenient() => {
+                if e.kind != ParsingErrorKind::MissingEndOfTrack {
+                    let position = e.position;
+                    warnings.push(options::ParseWarning::new(options::ParseWarningKind::InvalidTrackData(e), position));
+                }
+                break;
+            },
+            Some(Err(e)) => return Err(e),
+            None => break
         }
     }
 
-    Ok(chunk::Chunk::MTrk(events))
+    if !end_of_track {
+        warnings.push(options::ParseWarning::new(options::ParseWarningKind::MissingEndOfTrack, *i));
+    }
+
+    Ok(chunk::Track::new(events))
 }
 
-pub fn parse_midi_file(data: &[u8]) -> Result<chunk::MidiFile, ParsingError> {
+// Chunk types are made of 4 ASCII characters
+fn is_chunk_type(chunk_type: &[u8]) -> bool {
+    chunk_type.iter().all(|c| (0x20..=0x7E).contains(c))
+}
+
+// Where the next MTrk chunk starts, after `from`
+fn find_track_chunk(data: &[u8], from: usize) -> Option<usize> {
+    data.get(from..)?.windows(4).position(|w| w == b"MTrk").map(|p| from + p)
+}
+
+// Compares the number of tracks declared in the header with the actual one.
+fn validate_track_count(header: &chunk::Header, number_of_tracks: usize, header_position: usize) -> Vec<options::ParseWarning> {
+    use options::{ParseWarning, ParseWarningKind};
+
+    let mut warnings = Vec::<ParseWarning>::new();
+
+    if header.number_of_tracks as usize != number_of_tracks {
+        warnings.push(ParseWarning::new(ParseWarningKind::TrackCountMismatch { expected: header.number_of_tracks as usize, found: number_of_tracks }, header_position + 10));
+    }
+    if header.format == chunk::MidiFileFormat::SingleTrack && number_of_tracks > 1 {
+        warnings.push(ParseWarning::new(ParseWarningKind::MultipleTracksInSingleTrackFile { found: number_of_tracks }, header_position + 8));
+    }
+
+    warnings
+}
+
+// Besides the recovered errors of the lenient mode, the warnings report inconsistencies that don't prevent parsing
+// in either mode: a header not at the start of the file, a wrong number of tracks and undefined time and key signatures.
+pub fn parse_midi_file_with_options(data: &[u8], options: &options::ParseOptions) -> Result<(chunk::MidiFile, Vec<options::ParseWarning>), ParsingError> {
+    use options::{ParseWarning, ParseWarningKind};
+
+    let lossless = options.lossless;
+    let lenient = options.is_lenient();
+
     // Iterator
     let mut i: usize = 0;
     
-    let mut header: chunk::Chunk = chunk::Chunk::default_header();
-    let mut tracks = Vec::<chunk::Chunk>::new();
+    let mut header = chunk::Header::default();
+    let mut tracks = Vec::<chunk::Track>::new();
+    let mut unknown_chunks = Vec::<chunk::UnknownChunk>::new();
+    let mut encoding = chunk::FileEncoding::default();
+    let mut warnings = Vec::<ParseWarning>::new();
+    let mut header_position: Option<usize> = None;
 
     while i < data.len() {
+        // In lossless mode, anything that can't be a chunk at the end of the file is kept as trailing data
+        if (lossless || lenient) && data.len() - i < 8 {
+            if lossless {
+                encoding.trailing_data = data[i..].to_vec();
+            }
+            if lenient {
+                warnings.push(ParseWarning::new(ParseWarningKind::TrailingData { length: data.len() - i }, i));
+            }
+            break;
+        }
+
+        let chunk_start = i;
+
         // Chunk type
         let chunk_type_raw = match read_bytes_at(data, &mut i, 4) {
             Ok(t) => t,
-            Err(e) => return Err(ParsingError {
-                position: i,
-                message: format!("Not enough data to read MTrk.chunk_type\n{}", e)
-            })
+            Err(e) => return Err(ParsingError::eof("MTrk.chunk_type", e))
         };
 
         // Length
         let chunk_length_raw = match read_bytes_at(data, &mut i, 4) {
             Ok(l) => l,
-            Err(e) => return Err(ParsingError {
-                position: i,
-                message: format!("Not enough data to read MTrk.length\n{}", e)
-            })
+            Err(e) => return Err(ParsingError::eof("MTrk.length", e))
         };
         let chunk_length = u32::from_be_bytes(chunk_length_raw.try_into().unwrap()) as usize;
 
         match chunk_type_raw {
             b"MThd" => {
-                if chunk_length != chunk::MTHD_LENGTH {
-                    return Err(ParsingError {
-                        position: i,
-                        message: format!("The length of the header was not equal the expected one\nExpected: {}B\nFound: {}B", chunk::MTHD_LENGTH, chunk_length)
-                    });
+                if chunk_length < chunk::MTHD_LENGTH {
+                    if !lenient {
+                        return Err(ParsingError::new(ParsingErrorKind::InvalidChunkLength {
+                            chunk_type: *b"MThd",
+                            expected: chunk::MTHD_LENGTH,
+                            found: chunk_length
+                        }, i - 4));
+                    }
+
+                    warnings.push(ParseWarning::new(ParseWarningKind::InvalidHeaderLength { found: chunk_length }, i - 4));
+                    i = i.saturating_add(chunk_length).min(data.len());
+                    continue;
                 }
-                header = match parse_header_at(data, &mut i) {
-                    Ok(h) => h,
-                    Err(e) => return Err(e)
+
+                let mut header_end = i;
+                let parsed_header = parse_header_at(data, &mut header_end)?;
+
+                // Longer headers are allowed, the bytes after the known fields are ignored
+                let chunk_end = i.saturating_add(chunk_length);
+                let extra_data = match data.get(header_end..chunk_end) {
+                    Some(d) => d,
+                    None if lenient => &data[header_end..],
+                    None => return Err(ParsingError::eof("MThd", EOFError {
+                        position: header_end,
+                        tried_to_read: chunk_end - header_end,
+                        buffer_size: data.len()
+                    }))
                 };
+                i = chunk_end.min(data.len());
+
+                if header_position.is_some() {
+                    if !lenient {
+                        return Err(ParsingError::new(ParsingErrorKind::DuplicateHeader, chunk_start));
+                    }
+                    warnings.push(ParseWarning::new(ParseWarningKind::DuplicateHeader, chunk_start));
+                    continue;
+                }
+
+                if chunk_start != 0 {
+                    warnings.push(ParseWarning::new(ParseWarningKind::HeaderNotFirst, chunk_start));
+                }
+
+                header = parsed_header;
+                header_position = Some(chunk_start);
+                encoding.header_extra_data = extra_data.to_vec();
+                encoding.chunks.push(chunk::ChunkEncoding::Header);
             },
             b"MTrk" => {
-                let track = match parse_track_at(data, &mut i, chunk_length) {
+                let track_index = tracks.len();
+                let data_start = i;
+                let mut length = chunk_length;
+
+                if lenient && length > data.len() - data_start {
+                    warnings.push(ParseWarning::new(ParseWarningKind::TrackLengthClamped {
+                        declared: length,
+                        available: data.len() - data_start
+                    }, chunk_start + 4).at_track(track_index));
+                    length = data.len() - data_start;
+                }
+
+                let first_warning = warnings.len();
+                let track = match parse_track_with(data, &mut i, length, options, &mut warnings) {
                     Ok(trk) => trk,
-                    Err(e) => return Err(e)
+                    Err(e) => return Err(e.at_track(track_index))
                 };
+                for warning in &mut warnings[first_warning..] {
+                    warning.track_index = Some(track_index);
+                    if let ParseWarningKind::InvalidTrackData(e) = &mut warning.kind {
+                        e.track_index = Some(track_index);
+                    }
+                }
+
                 tracks.push(track);
+                encoding.chunks.push(chunk::ChunkEncoding::Track);
+
+                if lenient {
+                    // A track length too long swallows the following chunks, unless the track ended right before one of them
+                    let declared_end = data_start + length;
+                    if i < declared_end && data[i..].starts_with(b"MTrk") {
+                        warnings.push(ParseWarning::new(ParseWarningKind::TrackLengthMismatch {
+                            declared: chunk_length,
+                            actual: i - data_start
+                        }, chunk_start + 4).at_track(track_index));
+                    } else {
+                        let ended = tracks.last().is_some_and(chunk::Track::has_end_of_track);
+                        if ended && i < declared_end {
+                            warnings.push(ParseWarning::new(ParseWarningKind::DataAfterEndOfTrack { length: declared_end - i }, i).at_track(track_index));
+                        }
+                        i = declared_end;
+                    }
+                }
             },
             _ => {
-                // Ignore unknown chunks, following the documentation.
+                // Garbage instead of a chunk, the parsing resumes at the next track
+                if lenient && (!is_chunk_type(chunk_type_raw) || chunk_length > data.len() - i) {
+                    let next = find_track_chunk(data, chunk_start + 1).unwrap_or(data.len());
+                    warnings.push(ParseWarning::new(ParseWarningKind::SkippedData { length: next - chunk_start }, chunk_start));
+
+                    if lossless && next == data.len() {
+                        encoding.trailing_data = data[chunk_start..].to_vec();
+                    }
+                    i = next;
+                    continue;
+                }
+
+                // Unknown chunks are kept as they are, the documentation says to ignore them
+                let chunk_data = match data.get(i..i.saturating_add(chunk_length)) {
+                    Some(d) => d,
+                    None if lossless => {
+                        // Not a chunk after all
+                        encoding.trailing_data = data[chunk_start..].to_vec();
+                        break;
+                    },
+                    None => return Err(ParsingError::eof("UnknownChunk.data", EOFError {
+                        position: i,
+                        tried_to_read: chunk_length,
+                        buffer_size: data.len()
+                    }))
+                };
+
+                unknown_chunks.push(chunk::UnknownChunk {
+                    id: chunk_type_raw.try_into().unwrap(),
+                    data: chunk_data.to_vec()
+                });
+                encoding.chunks.push(chunk::ChunkEncoding::Unknown);
                 i += chunk_length;
             }
         }
     }
 
-    Ok(chunk::MidiFile {
+    match header_position {
+        Some(position) => warnings.extend(validate_track_count(&header, tracks.len(), position)),
+        None if lenient => warnings.push(ParseWarning::new(ParseWarningKind::MissingHeader, 0)),
+        None => return Err(ParsingError::new(ParsingErrorKind::MissingHeader, 0))
+    }
+
+    let midi_file = chunk::MidiFile {
         header,
-        tracks
-    })
+        tracks,
+        unknown_chunks,
+        encoding: if lossless { Some(encoding) } else { None }
+    };
+
+    Ok((midi_file, warnings))
+}
+
+pub fn parse_midi_file(data: &[u8]) -> Result<chunk::MidiFile, ParsingError> {
+    parse_midi_file_with_options(data, &options::ParseOptions::strict()).map(|(f, _)| f)
+}
+
+// Keeps everything needed to write the file back byte for byte: the encoding of every event,
+// unknown chunks, the chunk order and trailing data.
+pub fn parse_midi_file_lossless(data: &[u8]) -> Result<chunk::MidiFile, ParsingError> {
+    parse_midi_file_with_options(data, &options::ParseOptions { lossless: true, ..Default::default() }).map(|(f, _)| f)
+}
+
+// (name, bytes) of every file in "Test MIDIs", for the tests of all of the modules
+#[cfg(test)]
+pub(crate) fn read_test_midis() -> Vec<(String, Vec<u8>)> {
+    let mut files = Vec::<(String, Vec<u8>)>::new();
+
+    for name in ["Megalovania", "Nyan Cat", "Tetris", "U.N. Owen was her"] {
+        let path = format!("{}/Test MIDIs/{}.mid", env!("CARGO_MANIFEST_DIR"), name);
+        let data = match std::fs::read(&path) {
+            Ok(d) => d,
+            Err(e) => panic!("{path}: {e}")
+        };
+        files.push((name.to_string(), data));
+    }
+
+    files
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_header_ticks_per_quarter_note() {
+        let mut i: usize = 0;
+
+        let data1: [u8; 6] = [0x00, 0x01, 0x00, 0x03, 0x00, 0x60];
+        let result1 = match parse_header_at(&data1, &mut i) {
+            Ok(r) => r,
+            Err(e) => panic!("{e}")
+        };
+        if result1.number_of_tracks != 3 || result1.division != chunk::Division::TicksPerQuarterNote(96) {
+            panic!("1. fail: wrong parameters");
+        }
+    }
+
+    #[test]
+    fn test_header_smpte() {
+        let mut i: usize = 0;
+
+        // -25 fps, 40 ticks per frame (millisecond resolution)
+        let data1: [u8; 6] = [0x00, 0x00, 0x00, 0x01, 0xE7, 0x28];
+        let result1 = match parse_header_at(&data1, &mut i) {
+            Ok(r) => r,
+            Err(e) => panic!("{e}")
+        };
+        let division = result1.division;
+        if division != (chunk::Division::SMPTE { format: chunk::SMPTEFormat::Fps25, ticks_per_frame: 40 }) {
+            panic!("1. fail: wrong parameters");
+        }
+        if division.ticks_per_second() != Some(1000.0) || division.microseconds_per_tick(500000) != 1000.0 {
+            panic!("1. fail: wrong timing");
+        }
+        i = 0;
+
+        // -29 fps (29.97 drop-frame), 80 ticks per frame
+        let data2: [u8; 6] = [0x00, 0x00, 0x00, 0x01, 0xE3, 0x50];
+        let result2 = match parse_header_at(&data2, &mut i) {
+            Ok(r) => r,
+            Err(e) => panic!("{e}")
+        };
+        if let chunk::Division::SMPTE { format, ticks_per_frame } = result2.division {
+            if format != chunk::SMPTEFormat::Fps30DropFrame || ticks_per_frame != 80 || !format.is_drop_frame() {
+                panic!("2. fail: wrong parameters");
+            }
+        } else {
+            panic!("2. fail: wrong enum variant");
+        }
+        i = 0;
+
+        // -20 fps is not a valid SMPTE format
+        let data3: [u8; 6] = [0x00, 0x00, 0x00, 0x01, 0xEC, 0x50];
+        if parse_header_at(&data3, &mut i).is_ok() {
+            panic!("3. fail: invalid SMPTE format accepted");
+        }
+    }
+
+    #[test]
+    fn test_header_zero_division() {
+        // 0 ticks per quarter note, then -25 fps with 0 ticks per frame
+        for data in [[0x00, 0x00, 0x00, 0x01, 0x00, 0x00], [0x00, 0x00, 0x00, 0x01, 0xE7, 0x00]] {
+            let mut i: usize = 0;
+            match parse_header_at(&data, &mut i) {
+                Err(e) if e.kind == ParsingErrorKind::ZeroDivision && e.position == 4 => {},
+                other => panic!("1. fail: {:?}", other)
+            }
+        }
+    }
+
+    #[test]
+    fn test_running_status() {
+        // 90 3C 40, (3C) 00, (3E) 40 - the last two NoteOns reuse the status of the first one
+        let mut i: usize = 0;
+
+        let data1: [u8; 14] = [0x00, 0x90, 0x3C, 0x40, 0x10, 0x3C, 0x00, 0x00, 0x3E, 0x40, 0x00, 0xFF, 0x2F, 0x00];
+        let result1 = match parse_track_at(&data1, &mut i, 14, false) {
+            Ok(r) => r,
+            Err(e) => panic!("{e}")
+        };
+        let events = result1.into_events();
+        if events.len() != 4 || i != 14 {
+            panic!("1. fail: wrong number of events ({})", events.len());
+        }
+        for (event, (expected_delta_time, expected_key, expected_velocity)) in events.iter().zip([(0, 0x3C, 0x40), (0x10, 0x3C, 0x00), (0, 0x3E, 0x40)]) {
+            if let chunk::TrackEventType::Midi(midi_event::MidiEvent::NoteOn { channel, key, velocity }) = event.event {
+                if channel != 0 || key != expected_key || velocity != expected_velocity || event.delta_time != expected_delta_time {
+                    panic!("1. fail: wrong parameters");
+                }
+            } else {
+                panic!("1. fail: wrong enum variant");
+            }
+        }
+    }
+
+    #[test]
+    fn test_running_status_cancelled_by_meta_event() {
+        // 90 3C 40, FF 01 00, (3C) 00 - the meta-event cancels the running status
+        let mut i: usize = 0;
+
+        let data1: [u8; 15] = [0x00, 0x90, 0x3C, 0x40, 0x00, 0xFF, 0x01, 0x00, 0x00, 0x3C, 0x00, 0x00, 0xFF, 0x2F, 0x00];
+        match parse_track_at(&data1, &mut i, 15, false) {
+            Err(e) => if e.kind != ParsingErrorKind::MissingRunningStatus(0x3C) {
+                panic!("1. fail: wrong error ({e})");
+            },
+            Ok(_) => panic!("1. fail: data byte accepted without a running status")
+        }
+    }
+
+    #[test]
+    fn test_running_status_without_status() {
+        let mut i: usize = 0;
+
+        let data1: [u8; 3] = [0x00, 0x3C, 0x40];
+        if parse_track_at(&data1, &mut i, 3, false).is_ok() {
+            panic!("1. fail: data byte accepted without a running status");
+        }
+    }
+
+    #[test]
+    fn test_parse_test_midis() {
+        for (name, data) in read_test_midis() {
+            if let Err(e) = parse_midi_file(&data) {
+                panic!("{name}: {e}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_truncated_test_midis() {
+        // Any truncation of a file must produce an error instead of a panic
+        for (name, data) in read_test_midis() {
+            let step = data.len() / 2000 + 1;
+
+            for length in (0..data.len()).step_by(step) {
+                if let Ok(f) = parse_midi_file(&data[..length]) {
+                    // Cutting exactly at a chunk boundary leaves a valid file with fewer tracks
+                    if f.tracks.is_empty() && length > chunk::MTHD_LENGTH + 8 {
+                        panic!("{name}: truncated to {length}B parsed without tracks");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_corrupted_test_midis() {
+        // Overwrite random bytes of each file; the parser may fail but must not panic.
+        // A fixed-seed LCG keeps the corpus reproducible.
+        let mut seed: u64 = 0x5EED;
+        let mut next_random = || {
+            seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (seed >> 33) as usize
+        };
+
+        for (_, data) in read_test_midis() {
+            for _ in 0..500 {
+                let mut corrupted = data.clone();
+
+                for _ in 0..(next_random() % 8 + 1) {
+                    let position = next_random() % corrupted.len();
+                    corrupted[position] = next_random() as u8;
+                }
+
+                let _ = parse_midi_file(&corrupted);
+            }
+        }
+    }
+
+    #[test]
+    fn test_undefined_event_codes_in_track() {
+        for code in [0xF1, 0xF4, 0xF5, 0xF9, 0xFD] {
+            let mut i: usize = 0;
+
+            let data1: [u8; 4] = [0x00, code, 0x00, 0x00];
+            if parse_track_at(&data1, &mut i, 4, false).is_ok() {
+                panic!("1. fail: undefined event code {:X} accepted", code);
+            }
+        }
+
+        // FF 2F 01 00 - meta-events with an invalid length aren't parsed as a Reset message
+        let mut i: usize = 0;
+
+        let data2: [u8; 5] = [0x00, 0xFF, 0x2F, 0x01, 0x00];
+        if parse_track_at(&data2, &mut i, 5, false).is_ok() {
+            panic!("2. fail: invalid meta-event accepted");
+        }
+    }
+
+    #[test]
+    fn test_error_kind() {
+        // MThd, MTrk with one valid event, MTrk with an undefined event code as the third event
+        let data1: [u8; 43] = [
+            0x4D, 0x54, 0x68, 0x64, 0x00, 0x00, 0x00, 0x06, 0x00, 0x01, 0x00, 0x02, 0x00, 0x60,
+            0x4D, 0x54, 0x72, 0x6B, 0x00, 0x00, 0x00, 0x04, 0x00, 0xFF, 0x2F, 0x00,
+            0x4D, 0x54, 0x72, 0x6B, 0x00, 0x00, 0x00, 0x09, 0x00, 0x90, 0x3C, 0x40, 0x00, 0x3C, 0x00, 0x00, 0xF4
+        ];
+        let error1 = match parse_midi_file(&data1) {
+            Ok(_) => panic!("1. fail: undefined event code accepted"),
+            Err(e) => e
+        };
+        if error1.kind != ParsingErrorKind::UnknownStatusByte(0xF4) || error1.position != 43 || error1.track_index != Some(1) || error1.event_index != Some(2) {
+            panic!("1. fail: wrong error ({error1})");
+        }
+
+        // A truncated NoteOn, the EOFError is kept as the source
+        let error2 = match parse_midi_file(&data1[..37]) {
+            Ok(_) => panic!("2. fail: truncated file accepted"),
+            Err(e) => e
+        };
+        if !matches!(error2.kind, ParsingErrorKind::UnexpectedEOF { field: "MidiEvent[NoteOn].velocity", .. }) || error2.track_index != Some(1) || error2.event_index != Some(0) {
+            panic!("2. fail: wrong error ({error2})");
+        }
+        if std::error::Error::source(&error2).is_none() {
+            panic!("2. fail: no source");
+        }
+
+        // A meta-event with an invalid length
+        let mut i: usize = 0;
+        let data3: [u8; 5] = [0x00, 0xFF, 0x51, 0x01, 0x00];
+        let error3 = match parse_track_at(&data3, &mut i, 5, false) {
+            Ok(_) => panic!("3. fail: invalid meta-event accepted"),
+            Err(e) => e
+        };
+        if error3.kind != (ParsingErrorKind::InvalidMetaLength { code: 0x51, expected: 3, found: 1 }) || error3.event_index != Some(0) {
+            panic!("3. fail: wrong error ({error3})");
+        }
+
+        // A variable-length quantity longer than 4 bytes
+        i = 0;
+        let data4: [u8; 6] = [0x80, 0x80, 0x80, 0x80, 0x00, 0x00];
+        let error4 = match parse_track_at(&data4, &mut i, 6, false) {
+            Ok(_) => panic!("4. fail: invalid delta time accepted"),
+            Err(e) => e
+        };
+        if error4.kind != ParsingErrorKind::InvalidVariableLength {
+            panic!("4. fail: wrong error ({error4})");
+        }
+    }
+
+    #[test]
+    fn test_lenient_parsing() {
+        use options::*;
+
+        let data: Vec<u8> = vec![
+            b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 1, 0, 3, 0, 0x60,
+            // Declared as 100B long
+            b'M', b'T', b'r', b'k', 0, 0, 0, 100, 0x00, 0x90, 0x3C, 0x40, 0x60, 0x80, 0x3C, 0x00, 0x00, 0xFF, 0x2F, 0x00,
+            b'M', b'T', b'r', b'k', 0, 0, 0, 4, 0x00, 0xFF, 0x2F, 0x00,
+            // Garbage
+            0x01, 0x02, 0x03, 0x04, 0x05,
+            // Truncated
+            b'M', b'T', b'r', b'k', 0, 0, 0, 50, 0x00, 0x90, 0x3C, 0x40, 0x00, 0x90, 0x3C
+        ];
+
+        if parse_midi_file(&data).is_ok() {
+            panic!("1. fail: parsed in strict mode");
+        }
+
+        let (midi_file, warnings) = match parse_midi_file_with_options(&data, &ParseOptions::lenient()) {
+            Ok(r) => r,
+            Err(e) => panic!("{e}")
+        };
+        let lengths: Vec<usize> = midi_file.tracks.iter().map(|t| t.events().len()).collect();
+        if lengths != vec![3, 1, 1] {
+            panic!("2. fail: wrong tracks {:?}", lengths);
+        }
+
+        let kinds: Vec<(usize, Option<usize>)> = warnings.iter().map(|w| (w.position, w.track_index)).collect();
+        if kinds != vec![(18, Some(0)), (18, Some(0)), (46, None), (55, Some(2)), (66, Some(2)), (66, Some(2))] {
+            panic!("3. fail: wrong warnings {:?}", warnings);
+        }
+        if warnings[0].kind != (ParseWarningKind::TrackLengthClamped { declared: 100, available: 44 })
+            || warnings[1].kind != (ParseWarningKind::TrackLengthMismatch { declared: 100, actual: 12 })
+            || warnings[2].kind != (ParseWarningKind::SkippedData { length: 5 })
+            || warnings[3].kind != (ParseWarningKind::TrackLengthClamped { declared: 50, available: 7 })
+            || warnings[5].kind != ParseWarningKind::MissingEndOfTrack {
+            panic!("3. fail: wrong warnings {:?}", warnings);
+        }
+        match &warnings[4].kind {
+            ParseWarningKind::InvalidTrackData(e) => if e.track_index != Some(2) || e.event_index != Some(1) {
+                panic!("4. fail: wrong error {e}");
+            },
+            _ => panic!("4. fail: wrong warning {}", warnings[4])
+        }
+
+        // Valid files don't produce warnings
+        for (name, data) in read_test_midis() {
+            match parse_midi_file_with_options(&data, &ParseOptions::lenient()) {
+                Ok((midi_file, warnings)) => if !warnings.is_empty() || Ok(midi_file) != parse_midi_file(&data) {
+                    panic!("{name}: {:?}", warnings);
+                },
+                Err(e) => panic!("{name}: {e}")
+            }
+        }
+    }
+
+    #[test]
+    fn test_track_boundaries() {
+        // 90 3C 40, FF 2F 00 with the track length in the 4th byte of the header
+        let track = |length: u8, events: &[u8]| -> Vec<u8> {
+            let mut data = vec![b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0, 1, 0, 0x60, b'M', b'T', b'r', b'k', 0, 0, 0, length];
+            data.extend_from_slice(events);
+            data
+        };
+        let valid = [0x00, 0x90, 0x3C, 0x40, 0x00, 0xFF, 0x2F, 0x00];
+
+        if let Err(e) = parse_midi_file(&track(8, &valid)) {
+            panic!("1. fail: {e}");
+        }
+
+        // The length cuts EndOfTrack in half, the rest of it is followed by an unknown chunk
+        let mut data2 = track(6, &valid);
+        data2.extend_from_slice(b"XXXX\x00\x00\x00\x00");
+        match parse_midi_file(&data2) {
+            Err(e) => if e.kind != (ParsingErrorKind::TrackOverrun { length: 6 }) || e.position != 26 || e.event_index != Some(1) {
+                panic!("2. fail: wrong error ({e})");
+            },
+            Ok(_) => panic!("2. fail: overrun accepted")
+        }
+
+        // 2 bytes of padding after EndOfTrack
+        match parse_midi_file(&track(10, &[0x00, 0x90, 0x3C, 0x40, 0x00, 0xFF, 0x2F, 0x00, 0x80, 0x80])) {
+            Err(e) => if e.kind != (ParsingErrorKind::TrackUnderrun { length: 10, used: 8 }) || e.position != 30 {
+                panic!("3. fail: wrong error ({e})");
+            },
+            Ok(_) => panic!("3. fail: underrun accepted")
+        }
+
+        // A NoteOff after EndOfTrack
+        match parse_midi_file(&track(12, &[0x00, 0x90, 0x3C, 0x40, 0x00, 0xFF, 0x2F, 0x00, 0x00, 0x80, 0x3C, 0x40])) {
+            Err(e) => if e.kind != ParsingErrorKind::EventAfterEndOfTrack || e.position != 30 || e.event_index != Some(2) {
+                panic!("4. fail: wrong error ({e})");
+            },
+            Ok(_) => panic!("4. fail: event after EndOfTrack accepted")
+        }
+
+        // No EndOfTrack
+        match parse_midi_file(&track(4, &valid[..4])) {
+            Err(e) => if e.kind != ParsingErrorKind::MissingEndOfTrack || e.position != 26 {
+                panic!("5. fail: wrong error ({e})");
+            },
+            Ok(_) => panic!("5. fail: missing EndOfTrack accepted")
+        }
+
+        // The file ends between two events, before the end of the track
+        match parse_midi_file(&track(8, &valid[..4])) {
+            Err(e) => if !matches!(e.kind, ParsingErrorKind::UnexpectedEOF { field: "MTrk.data", .. }) {
+                panic!("6. fail: wrong error ({e})");
+            },
+            Ok(_) => panic!("6. fail: truncated track accepted")
+        }
+
+        // The lenient mode skips the data after EndOfTrack
+        match parse_midi_file_with_options(&track(10, &[0x00, 0x90, 0x3C, 0x40, 0x00, 0xFF, 0x2F, 0x00, 0x80, 0x80]), &options::ParseOptions::lenient()) {
+            Ok((_, warnings)) => if warnings.len() != 1 || warnings[0].kind != (options::ParseWarningKind::DataAfterEndOfTrack { length: 2 }) {
+                panic!("7. fail: wrong warnings {:?}", warnings);
+            },
+            Err(e) => panic!("7. fail: {e}")
+        }
+    }
+
+    #[test]
+    fn test_header_validation() {
+        use options::*;
+
+        let header = |length: u8, format: u8, number_of_tracks: u8| -> Vec<u8> {
+            let mut data = vec![b'M', b'T', b'h', b'd', 0, 0, 0, length, 0, format, 0, number_of_tracks, 0, 0x60];
+            data.resize(8 + length as usize, 0xAA);
+            data
+        };
+        let track: [u8; 12] = [b'M', b'T', b'r', b'k', 0, 0, 0, 4, 0x00, 0xFF, 0x2F, 0x00];
+        let warning_kinds = |data: &[u8], options: &ParseOptions| -> Result<Vec<ParseWarningKind>, ParsingError> {
+            parse_midi_file_with_options(data, options).map(|(_, warnings)| warnings.into_iter().map(|w| w.kind).collect())
+        };
+
+        // A longer header, the extra bytes are kept in lossless mode
+        let data1 = [header(8, 0, 1), track.to_vec()].concat();
+        if warning_kinds(&data1, &ParseOptions::strict()) != Ok(vec![]) {
+            panic!("1. fail: longer header rejected");
+        }
+        match parse_midi_file_lossless(&data1).map(|f| crate::writer::midi_file_to_bytes(&f)) {
+            Ok(Ok(bytes)) => if bytes != data1 {
+                panic!("1. fail: wrong round trip");
+            },
+            _ => panic!("1. fail: lossless parsing")
+        }
+
+        // A shorter header
+        match parse_midi_file(&[header(4, 0, 1), track.to_vec()].concat()) {
+            Err(e) => if e.kind != (ParsingErrorKind::InvalidChunkLength { chunk_type: *b"MThd", expected: 6, found: 4 }) {
+                panic!("2. fail: wrong error ({e})");
+            },
+            Ok(_) => panic!("2. fail: shorter header accepted")
+        }
+
+        // No header
+        if parse_midi_file(&track).map_err(|e| e.kind) != Err(ParsingErrorKind::MissingHeader) {
+            panic!("3. fail: missing header accepted");
+        }
+        if warning_kinds(&track, &ParseOptions::lenient()) != Ok(vec![ParseWarningKind::MissingHeader]) {
+            panic!("3. fail: wrong warnings");
+        }
+
+        // Two headers, the first one is used
+        let data4 = [header(6, 1, 1), track.to_vec(), header(6, 2, 1)].concat();
+        if parse_midi_file(&data4).map_err(|e| (e.kind, e.position)) != Err((ParsingErrorKind::DuplicateHeader, 26)) {
+            panic!("4. fail: duplicate header accepted");
+        }
+        match parse_midi_file_with_options(&data4, &ParseOptions::lenient()) {
+            Ok((midi_file, warnings)) => if midi_file.header.format != chunk::MidiFileFormat::SimultaneousTracks
+                || warnings.iter().map(|w| &w.kind).collect::<Vec<_>>() != vec![&ParseWarningKind::DuplicateHeader] {
+                panic!("4. fail: wrong header");
+            },
+            Err(e) => panic!("4. fail: {e}")
+        }
+
+        // Wrong numbers of tracks, reported in both modes
+        let data5 = [header(6, 0, 3), track.to_vec(), track.to_vec()].concat();
+        for options in [ParseOptions::strict(), ParseOptions::lenient()] {
+            if warning_kinds(&data5, &options) != Ok(vec![
+                ParseWarningKind::TrackCountMismatch { expected: 3, found: 2 },
+                ParseWarningKind::MultipleTracksInSingleTrackFile { found: 2 }
+            ]) {
+                panic!("5. fail: wrong warnings");
+            }
+        }
+
+        // An unknown chunk before the header
+        let data6 = [b"XFIH\x00\x00\x00\x00".to_vec(), header(6, 0, 1), track.to_vec()].concat();
+        if warning_kinds(&data6, &ParseOptions::strict()) != Ok(vec![ParseWarningKind::HeaderNotFirst]) {
+            panic!("6. fail: wrong warnings");
+        }
+    }
+
+    #[test]
+    fn test_unknown_chunks() {
+        let data: Vec<u8> = [
+            &[b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 1, 0, 1, 0, 0x60][..],
+            &[b'X', b'F', b'I', b'H', 0, 0, 0, 3, 1, 2, 3],
+            &[b'M', b'T', b'r', b'k', 0, 0, 0, 4, 0x00, 0xFF, 0x2F, 0x00],
+            &[b'A', b'B', b'C', b'D', 0, 0, 0, 0]
+        ].concat();
+
+        // Kept in order, also without the lossless mode
+        let midi_file = match parse_midi_file(&data) {
+            Ok(f) => f,
+            Err(e) => panic!("{e}")
+        };
+        if midi_file.unknown_chunks != vec![
+            chunk::UnknownChunk { id: *b"XFIH", data: vec![1, 2, 3] },
+            chunk::UnknownChunk { id: *b"ABCD", data: vec![] }
+        ] {
+            panic!("1. fail: wrong unknown chunks {:?}", midi_file.unknown_chunks);
+        }
+
+        // Written back in their original place by the lossless mode
+        match parse_midi_file_lossless(&data).map(|f| crate::writer::midi_file_to_bytes(&f)) {
+            Ok(Ok(bytes)) => if bytes != data {
+                panic!("2. fail: wrong round trip {:X?}", bytes);
+            },
+            _ => panic!("2. fail: lossless parsing")
+        }
+
+        // A length past the end of the file
+        let mut data3 = data.clone();
+        data3[21] = 0xFF;
+        data3[20] = 0xFF;
+        match parse_midi_file(&data3) {
+            Err(e) => if !matches!(e.kind, ParsingErrorKind::UnexpectedEOF { .. }) || e.position != 22 {
+                panic!("3. fail: wrong error ({e})");
+            },
+            Ok(_) => panic!("3. fail: truncated chunk accepted")
+        }
+    }
+
+    #[test]
+    fn test_invalid_signatures() {
+        use options::*;
+
+        // 8 sharps, C minor, then 0/4
+        let data: Vec<u8> = [
+            &[b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0, 1, 0, 0x60][..],
+            &[b'M', b'T', b'r', b'k', 0, 0, 0, 24, 0x00, 0xFF, 0x59, 0x02, 0x08, 0x00, 0x00, 0xFF, 0x59, 0x02, 0xFD, 0x01],
+            &[0x00, 0xFF, 0x58, 0x04, 0x00, 0x02, 0x18, 0x08, 0x00, 0xFF, 0x2F, 0x00]
+        ].concat();
+
+        // Kept in both modes, with a warning
+        for options in [ParseOptions::strict(), ParseOptions::lenient()] {
+            let (midi_file, warnings) = match parse_midi_file_with_options(&data, &options) {
+                Ok(r) => r,
+                Err(e) => panic!("{e}")
+            };
+            let events = midi_file.tracks[0].events();
+            if events.len() != 4 || events[0].event != chunk::TrackEventType::Meta(meta_event::MetaEvent::InvalidKeySignature { sf: 8, mi: 0 }) {
+                panic!("1. fail: wrong events {:?}", events);
+            }
+            if warnings != vec![
+                ParseWarning::new(ParseWarningKind::InvalidKeySignature { sf: 8, mi: 0 }, 22).at_track(0),
+                ParseWarning::new(ParseWarningKind::InvalidTimeSignature { numerator: 0, denominator_exponent: 2 }, 34).at_track(0)
+            ] {
+                panic!("1. fail: wrong warnings {:?}", warnings);
+            }
+        }
+
+        // Written back as they were
+        match parse_midi_file_lossless(&data).map(|f| crate::writer::midi_file_to_bytes(&f)) {
+            Ok(Ok(bytes)) => if bytes != data {
+                panic!("2. fail: wrong round trip {:X?}", bytes);
+            },
+            _ => panic!("2. fail: lossless parsing")
+        }
+    }
+
 }
@@ -0,0 +1,142 @@
+use super::error::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ParseMode {
+    // Any malformed data is an error
+    #[default]
+    Strict,
+    // Recovers from malformed data where possible, reporting what was recovered as warnings
+    Lenient
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ParseOptions {
+    pub mode: ParseMode,
+    // Keeps everything needed to write the file back byte for byte: the encoding of every event,
+    // unknown chunks, the chunk order and trailing data.
+    // Data skipped by the lenient mode is not kept.
+    pub lossless: bool
+}
+
+impl ParseOptions {
+    pub fn strict() -> Self {
+        Self { mode: ParseMode::Strict, lossless: false }
+    }
+
+    pub fn lenient() -> Self {
+        Self { mode: ParseMode::Lenient, lossless: false }
+    }
+
+    pub fn is_lenient(&self) -> bool {
+        self.mode == ParseMode::Lenient
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseWarningKind {
+    // Data that isn't a chunk, skipped until the next MTrk or the end of the file
+    SkippedData {
+        length: usize
+    },
+    // An MThd chunk shorter than 6 bytes, skipped
+    InvalidHeaderLength {
+        found: usize
+    },
+    // No MThd chunk, the default header is used
+    MissingHeader,
+    // Another MThd chunk after the first one, skipped
+    DuplicateHeader,
+    // An MThd chunk that isn't the first chunk of the file
+    HeaderNotFirst,
+    // The number of tracks in the header differs from the number of MTrk chunks
+    TrackCountMismatch {
+        expected: usize,
+        found: usize
+    },
+    // A format 0 file with more than one track
+    MultipleTracksInSingleTrackFile {
+        found: usize
+    },
+    // A track longer than the rest of the file
+    TrackLengthClamped {
+        declared: usize,
+        available: usize
+    },
+    // A track ending with EndOfTrack before its declared length, directly followed by the next MTrk
+    TrackLengthMismatch {
+        declared: usize,
+        actual: usize
+    },
+    // The rest of the track couldn't be parsed, the events before the error are kept
+    InvalidTrackData(ParsingError),
+    MissingEndOfTrack,
+    // Data between EndOfTrack and the declared end of the track, skipped
+    DataAfterEndOfTrack {
+        length: usize
+    },
+    // A time signature with a numerator of 0 or a denominator too large for a u32, kept as `MetaEvent::InvalidTimeSignature`
+    InvalidTimeSignature {
+        numerator: u8,
+        denominator_exponent: u8
+    },
+    // A key signature with more than 7 sharps or flats or an undefined mode, kept as `MetaEvent::InvalidKeySignature`
+    InvalidKeySignature {
+        sf: i8,
+        mi: u8
+    },
+    // Less than a chunk header at the end of the file
+    TrailingData {
+        length: usize
+    }
+}
+
+impl std::fmt::Display for ParseWarningKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::SkippedData { length } => write!(f, "Skipped {}B of data outside of any chunk", length),
+            Self::InvalidHeaderLength { found } => write!(f, "The length of MThd was {}B instead of at least {}B", found, super::chunk::MTHD_LENGTH),
+            Self::MissingHeader => write!(f, "The file has no MThd chunk"),
+            Self::DuplicateHeader => write!(f, "Skipped a second MThd chunk"),
+            Self::HeaderNotFirst => write!(f, "The MThd chunk isn't the first chunk of the file"),
+            Self::TrackCountMismatch { expected, found } => write!(f, "The header declares {} tracks, but the file has {}", expected, found),
+            Self::MultipleTracksInSingleTrackFile { found } => write!(f, "A single track (format 0) file has {} tracks", found),
+            Self::TrackLengthClamped { declared, available } => write!(f, "The track length of {}B was clamped to the {}B left in the file", declared, available),
+            Self::TrackLengthMismatch { declared, actual } => write!(f, "The track ended after {}B instead of the declared {}B", actual, declared),
+            Self::InvalidTrackData(e) => write!(f, "The rest of the track was dropped\n{}", e),
+            Self::MissingEndOfTrack => write!(f, "The track doesn't end with EndOfTrack"),
+            Self::DataAfterEndOfTrack { length } => write!(f, "Skipped {}B after EndOfTrack", length),
+            Self::InvalidTimeSignature { numerator, denominator_exponent } => write!(f, "Undefined time signature: {}/2^{}\nThe numerator can't be 0 and the denominator can be at most 2^{}", numerator, denominator_exponent, super::time_signature::MAX_DENOMINATOR_EXPONENT),
+            Self::InvalidKeySignature { sf, mi } => write!(f, "Undefined key signature: sf = {}, mi = {}\nsf can only be between -7 and 7, mi can only be 0 or 1", sf, mi),
+            Self::TrailingData { length } => write!(f, "Ignored {}B of trailing data", length)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseWarning {
+    pub kind: ParseWarningKind,
+    pub position: usize,
+    // The index of the track (counting only MTrk chunks), if the warning is about a track
+    pub track_index: Option<usize>
+}
+
+impl ParseWarning {
+    pub fn new(kind: ParseWarningKind, position: usize) -> Self {
+        Self { kind, position, track_index: None }
+    }
+
+    pub fn at_track(mut self, track_index: usize) -> Self {
+        self.track_index = Some(track_index);
+        self
+    }
+}
+
+impl std::fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "ParseWarning {{\n\tPosition: {}B", self.position)?;
+        if let Some(track_index) = self.track_index {
+            write!(f, "\n\tTrack: {}", track_index)?;
+        }
+        write!(f, "\n\tMessage: \"{}\"\n}}", self.kind)
+    }
+}
@@ -0,0 +1,565 @@
+use std::io::Read;
+
+use super::error::*;
+use super::chunk;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StreamItem {
+    Header(chunk::Header),
+    TrackStart {
+        track_index: usize,
+        length: usize
+    },
+    Event(chunk::TrackEvent),
+    TrackEnd {
+        track_index: usize
+    },
+    // The data of unknown chunks is skipped without being stored
+    UnknownChunk {
+        id: [u8; 4],
+        length: usize
+    }
+}
+
+#[derive(Debug)]
+pub enum StreamError {
+    Io(std::io::Error),
+    Parsing(ParsingError)
+}
+
+impl std::fmt::Display for StreamError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{}", e),
+            Self::Parsing(e) => write!(f, "{}", e)
+        }
+    }
+}
+
+impl std::error::Error for StreamError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Parsing(e) => Some(e)
+        }
+    }
+}
+
+impl From<std::io::Error> for StreamError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<ParsingError> for StreamError {
+    fn from(e: ParsingError) -> Self {
+        Self::Parsing(e)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct TrackState {
+    track_index: usize,
+    // The position where the track data starts
+    start: usize,
+    length: usize,
+    running_status: Option<u8>,
+    event_index: usize,
+    end_of_track: bool
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Chunks,
+    Track(TrackState),
+    Done
+}
+
+// The largest number of bytes added to the buffer at once, the rest is only allocated once it arrives
+const READ_CHUNK_SIZE: usize = 8192;
+
+// The number of data bytes following a status byte, for the messages with a fixed length.
+fn data_length(status: u8) -> usize {
+    match status {
+        0x80..=0xBF | 0xE0..=0xEF => 2,
+        0xC0..=0xDF => 1,
+        0xF2 => 2,
+        0xF3 => 1,
+        _ => 0
+    }
+}
+
+// A pull-based parser reading one chunk header or track event at a time from any `Read`,
+// the memory used only depends on the size of the largest event.
+// Wrap unbuffered readers (files, pipes) in a `BufReader`, the data is read a few bytes at a time.
+//
+// The items follow the layout of the file, every track is a TrackStart, its events and a TrackEnd.
+// Like `parse_midi_file`, the file needs exactly one MThd chunk, which doesn't have to come first.
+// Parsing stops at the first error.
+#[derive(Debug)]
+pub struct MidiStreamReader<R: Read> {
+    reader: R,
+    // The number of bytes read from the start of the stream
+    position: usize,
+    state: State,
+    number_of_tracks: usize,
+    header_read: bool,
+    // The bytes of the event being read, reused between events
+    buffer: Vec<u8>
+}
+
+impl<R: Read> MidiStreamReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            position: 0,
+            state: State::Chunks,
+            number_of_tracks: 0,
+            header_read: false,
+            buffer: Vec::new()
+        }
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+
+    // Appends up to `count` bytes to the buffer, returning the number of bytes read.
+    fn read_up_to(&mut self, count: usize) -> Result<usize, std::io::Error> {
+        let start = self.buffer.len();
+
+        let mut read: usize = 0;
+        while read < count {
+            let chunk_size = (count - read).min(READ_CHUNK_SIZE);
+
+            match (&mut self.reader).take(chunk_size as u64).read_to_end(&mut self.buffer) {
+                Ok(n) => {
+                    read += n;
+                    if n < chunk_size {
+                        break;
+                    }
+                },
+                Err(e) => {
+                    self.buffer.truncate(start);
+                    return Err(e);
+                }
+            }
+        }
+
+        self.position += read;
+        Ok(read)
+    }
+
+    fn read_exact(&mut self, count: usize, field: &'static str) -> Result<(), StreamError> {
+        let position = self.position;
+        let read = self.read_up_to(count)?;
+
+        if read < count {
+            return Err(ParsingError::eof(field, EOFError {
+                position,
+                tried_to_read: count,
+                buffer_size: position + read
+            }).into());
+        }
+
+        Ok(())
+    }
+
+    // Reads the bytes of a variable-length quantity and returns its value, or None if it's longer than 4 bytes.
+    fn read_variable_length(&mut self, field: &'static str) -> Result<Option<u32>, StreamError> {
+        let mut res: u32 = 0;
+
+        // The 5th byte is read as well, for the parser to report the error
+        for _ in 0..5 {
+            self.read_exact(1, field)?;
+
+            let byte = self.buffer[self.buffer.len() - 1];
+            res = (res << 7) | ((byte & 0b01111111) as u32);
+
+            if byte & 0b10000000 == 0 {
+                return Ok(Some(res));
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn skip(&mut self, count: usize) -> Result<usize, std::io::Error> {
+        let skipped = std::io::copy(&mut (&mut self.reader).take(count as u64), &mut std::io::sink())? as usize;
+        self.position += skipped;
+        Ok(skipped)
+    }
+
+    // Reads the data of a meta-event or System Exclusive event, which can't go past the end of the track.
+    fn read_event_data(&mut self, length: u32, track: &TrackState, event_start: usize, field: &'static str) -> Result<(), StreamError> {
+        if self.position.saturating_add(length as usize) > track.start + track.length {
+            return Err(ParsingError::new(ParsingErrorKind::TrackOverrun { length: track.length }, event_start).into());
+        }
+
+        self.read_exact(length as usize, field)
+    }
+
+    // Reads the bytes of the next event into the buffer, using only the status byte to find its length.
+    fn read_event_bytes(&mut self, track: &TrackState, event_start: usize) -> Result<(), StreamError> {
+        if self.read_variable_length("TrackEvent.delta_time")?.is_none() {
+            return Ok(());
+        }
+
+        self.read_exact(1, "MidiEvent.event_code")?;
+        let first_byte = self.buffer[self.buffer.len() - 1];
+
+        match first_byte {
+            0xFF => {
+                self.read_exact(1, "MetaEvent.code")?;
+                if let Some(length) = self.read_variable_length("MetaEvent.length")? {
+                    self.read_event_data(length, track, event_start, "MetaEvent.data")?;
+                }
+            },
+            0xF0 | 0xF7 => {
+                if let Some(length) = self.read_variable_length("MidiEvent[SystemExclusive].length")? {
+                    self.read_event_data(length, track, event_start, "MidiEvent[SystemExclusive].data")?;
+                }
+            },
+            0x00..=0x7F => {
+                // Running status, the first byte was already data
+                if let Some(status) = track.running_status {
+                    self.read_exact(data_length(status) - 1, "MidiEvent.data")?;
+                }
+            },
+            status => self.read_exact(data_length(status), "MidiEvent.data")?
+        }
+
+        Ok(())
+    }
+
+    fn next_chunk(&mut self) -> Result<Option<StreamItem>, StreamError> {
+        let chunk_start = self.position;
+        self.buffer.clear();
+
+        // The stream can only end between chunks, after the header
+        if self.read_up_to(4)? == 0 {
+            if !self.header_read {
+                return Err(ParsingError::new(ParsingErrorKind::MissingHeader, 0).into());
+            }
+            return Ok(None);
+        }
+        if self.buffer.len() < 4 {
+            return Err(ParsingError::eof("MTrk.chunk_type", EOFError {
+                position: chunk_start,
+                tried_to_read: 4,
+                buffer_size: self.position
+            }).into());
+        }
+        self.read_exact(4, "MTrk.length")?;
+
+        let id: [u8; 4] = self.buffer[0..4].try_into().unwrap();
+        let length = u32::from_be_bytes(self.buffer[4..8].try_into().unwrap()) as usize;
+
+        match &id {
+            b"MThd" => {
+                if self.header_read {
+                    return Err(ParsingError::new(ParsingErrorKind::DuplicateHeader, chunk_start).into());
+                }
+
+                if length < chunk::MTHD_LENGTH {
+                    return Err(ParsingError::new(ParsingErrorKind::InvalidChunkLength {
+                        chunk_type: *b"MThd",
+                        expected: chunk::MTHD_LENGTH,
+                        found: length
+                    }, chunk_start + 4).into());
+                }
+
+                let header_start = self.position;
+                self.buffer.clear();
+                self.read_exact(chunk::MTHD_LENGTH, "MThd")?;
+
+                match super::parse_header_at(&self.buffer, &mut 0) {
+                    Ok(header) => {
+                        // Longer headers are allowed, the bytes after the known fields are ignored
+                        let extra_length = length - chunk::MTHD_LENGTH;
+                        if self.skip(extra_length)? < extra_length {
+                            return Err(ParsingError::eof("MThd", EOFError {
+                                position: header_start + chunk::MTHD_LENGTH,
+                                tried_to_read: extra_length,
+                                buffer_size: self.position
+                            }).into());
+                        }
+                        self.header_read = true;
+                        Ok(Some(StreamItem::Header(header)))
+                    },
+                    Err(mut e) => {
+                        e.position += header_start;
+                        Err(e.into())
+                    }
+                }
+            },
+            b"MTrk" => {
+                let track_index = self.number_of_tracks;
+                self.number_of_tracks += 1;
+                self.state = State::Track(TrackState {
+                    track_index,
+                    start: self.position,
+                    length,
+                    running_status: None,
+                    event_index: 0,
+                    end_of_track: false
+                });
+
+                Ok(Some(StreamItem::TrackStart { track_index, length }))
+            },
+            _ => {
+                let data_start = self.position;
+                if self.skip(length)? < length {
+                    return Err(ParsingError::eof("UnknownChunk.data", EOFError {
+                        position: data_start,
+                        tried_to_read: length,
+                        buffer_size: self.position
+                    }).into());
+                }
+                Ok(Some(StreamItem::UnknownChunk { id, length }))
+            }
+        }
+    }
+
+    fn next_event(&mut self, track: TrackState) -> Result<StreamItem, StreamError> {
+        let TrackState { track_index, start, length, mut running_status, event_index, end_of_track } = track;
+        let end = start + length;
+        let track_error = |e: ParsingError| StreamError::Parsing(e.at_event(event_index).at_track(track_index));
+
+        if self.position >= end {
+            if !end_of_track {
+                return Err(track_error(ParsingError::new(ParsingErrorKind::MissingEndOfTrack, self.position)));
+            }
+
+            self.state = State::Chunks;
+            return Ok(StreamItem::TrackEnd { track_index });
+        }
+
+        let event_start = self.position;
+        self.buffer.clear();
+
+        let res = match self.read_event_bytes(&track, event_start) {
+            Ok(()) => super::borrowed::parse_track_event_at(&self.buffer, &mut 0, &mut running_status).map(super::chunk::TrackEvent::from).map_err(|mut e| {
+                e.position += event_start;
+                if let ParsingErrorKind::UnexpectedEOF { source, .. } = &mut e.kind {
+                    source.position += event_start;
+                    source.buffer_size += event_start;
+                }
+                StreamError::Parsing(e)
+            }),
+            Err(e) => Err(e)
+        };
+
+        match res {
+            Ok(_) if end_of_track => Err(track_error(ParsingError::new(ParsingErrorKind::EventAfterEndOfTrack, event_start))),
+            Err(StreamError::Parsing(_)) if end_of_track => Err(track_error(ParsingError::new(ParsingErrorKind::TrackUnderrun { length, used: event_start - start }, event_start))),
+            Ok(_) if self.position > end => Err(track_error(ParsingError::new(ParsingErrorKind::TrackOverrun { length }, event_start))),
+            Ok(event) => {
+                let end_of_track = event.event == chunk::TrackEventType::Meta(super::meta_event::MetaEvent::EndOfTrack);
+                self.state = State::Track(TrackState { track_index, start, length, running_status, event_index: event_index + 1, end_of_track });
+                Ok(StreamItem::Event(event))
+            },
+            Err(StreamError::Parsing(e)) => Err(track_error(e)),
+            Err(e) => Err(e)
+        }
+    }
+}
+
+impl<R: Read> Iterator for MidiStreamReader<R> {
+    type Item = Result<StreamItem, StreamError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let res = match self.state {
+            State::Done => return None,
+            State::Chunks => self.next_chunk().transpose()?,
+            State::Track(track) => self.next_event(track)
+        };
+
+        if res.is_err() {
+            self.state = State::Done;
+        }
+
+        Some(res)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    // Hands out the data a few bytes at a time, like a pipe
+    struct SlowReader<'a> {
+        data: &'a [u8],
+        step: usize
+    }
+
+    impl Read for SlowReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.step = self.step % 7 + 1;
+            let count = self.step.min(buf.len()).min(self.data.len());
+            buf[..count].copy_from_slice(&self.data[..count]);
+            self.data = &self.data[count..];
+            Ok(count)
+        }
+    }
+
+    // (header, tracks, unknown chunk IDs)
+    type StreamedFile = (Option<chunk::Header>, Vec<chunk::Track>, Vec<[u8; 4]>);
+
+    fn read_stream(data: &[u8]) -> Result<StreamedFile, StreamError> {
+        let mut header = None;
+        let mut tracks = Vec::<chunk::Track>::new();
+        let mut unknown = Vec::<[u8; 4]>::new();
+        let mut events = Vec::<chunk::TrackEvent>::new();
+
+        for item in MidiStreamReader::new(SlowReader { data, step: 0 }) {
+            match item? {
+                StreamItem::Header(h) => header = Some(h),
+                StreamItem::TrackStart { .. } => events.clear(),
+                StreamItem::Event(e) => events.push(e),
+                StreamItem::TrackEnd { .. } => tracks.push(chunk::Track::new(std::mem::take(&mut events))),
+                StreamItem::UnknownChunk { id, .. } => unknown.push(id)
+            }
+        }
+
+        Ok((header, tracks, unknown))
+    }
+
+    #[test]
+    fn test_stream_test_midis() {
+        for (name, data) in crate::parser::read_test_midis() {
+            let midi_file = match super::super::parse_midi_file(&data) {
+                Ok(f) => f,
+                Err(e) => panic!("{name}: {e}")
+            };
+
+            let (header, tracks, _) = match read_stream(&data) {
+                Ok(r) => r,
+                Err(e) => panic!("{name}: {e}")
+            };
+            if header != Some(midi_file.header) || tracks != midi_file.tracks {
+                panic!("{name}: the streamed file differs");
+            }
+
+            // Truncated files fail at the same position as the whole-file parser
+            let truncated = &data[..data.len() * 2 / 3];
+            let expected = match super::super::parse_midi_file(truncated) {
+                Ok(_) => panic!("{name}: truncated file parsed"),
+                Err(e) => e
+            };
+            match read_stream(truncated) {
+                Err(StreamError::Parsing(e)) => if e.position != expected.position || e.track_index != expected.track_index || e.event_index != expected.event_index {
+                    panic!("{name}: wrong error\n{e}\n{expected}");
+                },
+                _ => panic!("{name}: truncated file streamed")
+            }
+        }
+    }
+
+    #[test]
+    fn test_stream_chunks() {
+        let data: Vec<u8> = vec![
+            b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0, 1, 0, 0x60,
+            b'X', b'Y', b'Z', b'W', 0, 0, 0, 3, 1, 2, 3,
+            b'M', b'T', b'r', b'k', 0, 0, 0, 12,
+            0x00, 0x90, 0x3C, 0x40,
+            0x60, 0x3C, 0x00,
+            0x81, 0x00, 0xFF, 0x2F, 0x00
+        ];
+
+        let (header, tracks, unknown) = match read_stream(&data) {
+            Ok(r) => r,
+            Err(e) => panic!("{e}")
+        };
+        let midi_file = match super::super::parse_midi_file(&data) {
+            Ok(f) => f,
+            Err(e) => panic!("{e}")
+        };
+        if header != Some(midi_file.header) || tracks != midi_file.tracks || unknown != vec![*b"XYZW"] {
+            panic!("1. fail: wrong items");
+        }
+
+        // An undefined status byte in the second event of the track
+        let mut data2 = data.clone();
+        data2[38] = 0xF4;
+        match read_stream(&data2) {
+            Err(StreamError::Parsing(e)) => if e.kind != ParsingErrorKind::UnknownStatusByte(0xF4) || e.position != 39 || (e.track_index, e.event_index) != (Some(0), Some(1)) {
+                panic!("2. fail: wrong error {e}");
+            },
+            _ => panic!("2. fail: no error")
+        }
+
+        // A track without EndOfTrack
+        let mut data3 = data[..40].to_vec();
+        data3[32] = 7;
+        match read_stream(&data3) {
+            Err(StreamError::Parsing(e)) => if e.kind != ParsingErrorKind::MissingEndOfTrack || e.position != 40 || Err(e) != super::super::parse_midi_file(&data3) {
+                panic!("3. fail: wrong error");
+            },
+            _ => panic!("3. fail: no error")
+        }
+    }
+
+    #[test]
+    fn test_stream_declared_lengths() {
+        let header: [u8; 14] = [b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0, 1, 0, 0x60];
+
+        // A System Exclusive event claiming 0x0FFFFFFF bytes in a 6-byte track
+        let mut data1 = header.to_vec();
+        data1.extend([b'M', b'T', b'r', b'k', 0, 0, 0, 6, 0x00, 0xF0, 0xFF, 0xFF, 0xFF, 0x7F]);
+        let mut reader = MidiStreamReader::new(data1.as_slice());
+        match reader.by_ref().find_map(|item| item.err()) {
+            Some(StreamError::Parsing(e)) => if e.kind != (ParsingErrorKind::TrackOverrun { length: 6 }) || e.position != 22 || e.track_index != Some(0) {
+                panic!("1. fail: wrong error {e}");
+            },
+            _ => panic!("1. fail: no error")
+        }
+        if reader.buffer.capacity() > READ_CHUNK_SIZE {
+            panic!("1. fail: {}B allocated for the event", reader.buffer.capacity());
+        }
+
+        // An unknown chunk going past the end of the file
+        let mut data2 = header.to_vec();
+        data2.extend([b'X', b'Y', b'Z', b'W', 0, 0, 0, 16, 1, 2, 3]);
+        let expected = match super::super::parse_midi_file(&data2) {
+            Ok(_) => panic!("2. fail: truncated chunk parsed"),
+            Err(e) => e
+        };
+        match read_stream(&data2) {
+            Err(StreamError::Parsing(e)) => if e != expected || !matches!(e.kind, ParsingErrorKind::UnexpectedEOF { field: "UnknownChunk.data", .. }) {
+                panic!("2. fail: wrong error\n{e}\n{expected}");
+            },
+            _ => panic!("2. fail: truncated chunk streamed")
+        }
+    }
+
+    #[test]
+    fn test_stream_header_checks() {
+        let header: [u8; 14] = [b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0, 1, 0, 0x60];
+        let track: [u8; 12] = [b'M', b'T', b'r', b'k', 0, 0, 0, 4, 0x00, 0xFF, 0x2F, 0x00];
+
+        for (data, expected) in [
+            (track.to_vec(), Some(ParsingErrorKind::MissingHeader)),
+            ([&header[..], &track, &header].concat(), Some(ParsingErrorKind::DuplicateHeader)),
+            // Accepted after the track, like by the whole-file parser
+            ([&track[..], &header].concat(), None)
+        ] {
+            let parsed = super::super::parse_midi_file(&data);
+            match (read_stream(&data), expected.clone()) {
+                (Err(StreamError::Parsing(e)), Some(kind)) => if e.kind != kind || Err(e) != parsed {
+                    panic!("fail: wrong error, expected {:?}", kind);
+                },
+                (Ok((header, tracks, _)), None) => if parsed.map(|f| (f.header, f.tracks)) != Ok((header.unwrap(), tracks)) {
+                    panic!("fail: the streamed file differs");
+                },
+                _ => panic!("fail: expected {:?}", expected)
+            }
+        }
+    }
+
+}
@@ -0,0 +1,326 @@
+use encoding_rs::{SHIFT_JIS, WINDOWS_1252};
+
+// RP-026 language tags at the start of a text meta-event
+const LATIN_TAG: &[u8] = b"{@LATIN}";
+const JAPANESE_TAG: &[u8] = b"{@JP}";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextEncoding {
+    Utf8,
+    // ISO-8859-1, every byte is the code point of the same value
+    Latin1,
+    // Latin-1 with printable characters in 80-9F, what most European files actually use
+    Windows1252,
+    // Including the Microsoft extensions of CP932
+    ShiftJis
+}
+
+impl TextEncoding {
+    // The encoding named by an RP-026 tag
+    pub fn from_tag(tag: &[u8]) -> Option<Self> {
+        match tag {
+            LATIN_TAG => Some(Self::Latin1),
+            JAPANESE_TAG => Some(Self::ShiftJis),
+            _ => None
+        }
+    }
+}
+
+// The text of a meta-event (01-07), kept as the bytes stored in the file.
+// The SMF specification doesn't define a character set, so the bytes are only decoded on demand.
+#[derive(Clone, PartialEq, Eq, Hash, Default)]
+pub struct Text {
+    bytes: Vec<u8>
+}
+
+impl Text {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self { bytes }
+    }
+
+    // `None` if the text has characters that the encoding can't represent
+    pub fn encode(text: &str, encoding: TextEncoding) -> Option<Self> {
+        let bytes = match encoding {
+            TextEncoding::Utf8 => text.as_bytes().to_vec(),
+            TextEncoding::Latin1 => text.chars().map(|c| u8::try_from(c).ok()).collect::<Option<Vec<u8>>>()?,
+            TextEncoding::Windows1252 => encode_with(WINDOWS_1252, text)?,
+            TextEncoding::ShiftJis => encode_with(SHIFT_JIS, text)?
+        };
+
+        Some(Self { bytes })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    // The encoding named by an RP-026 tag at the start of the text
+    pub fn tag(&self) -> Option<TextEncoding> {
+        [LATIN_TAG, JAPANESE_TAG].into_iter()
+            .find(|tag| self.bytes.starts_with(tag))
+            .and_then(TextEncoding::from_tag)
+    }
+
+    // The bytes after the RP-026 tag, if there is one
+    pub fn content(&self) -> &[u8] {
+        match self.tag() {
+            Some(TextEncoding::Latin1) => &self.bytes[LATIN_TAG.len()..],
+            Some(_) => &self.bytes[JAPANESE_TAG.len()..],
+            None => &self.bytes
+        }
+    }
+
+    // The encoding of the tag, or else the one guessed from the bytes
+    pub fn detect_encoding(&self) -> TextEncoding {
+        self.tag().unwrap_or_else(|| detect_encoding(self.content()))
+    }
+
+    // Decodes the text after the tag, `None` if it isn't valid in the encoding
+    pub fn decode(&self, encoding: TextEncoding) -> Option<String> {
+        let content = self.content();
+
+        match encoding {
+            TextEncoding::Utf8 => String::from_utf8(content.to_vec()).ok(),
+            TextEncoding::Latin1 => Some(content.iter().map(|&b| b as char).collect()),
+            TextEncoding::Windows1252 => WINDOWS_1252.decode_without_bom_handling_and_without_replacement(content).map(|s| s.into_owned()),
+            TextEncoding::ShiftJis => SHIFT_JIS.decode_without_bom_handling_and_without_replacement(content).map(|s| s.into_owned())
+        }
+    }
+
+    // Decodes the text after the tag, replacing invalid sequences with U+FFFD
+    pub fn decode_lossy(&self, encoding: TextEncoding) -> String {
+        let content = self.content();
+
+        match encoding {
+            TextEncoding::Utf8 => String::from_utf8_lossy(content).into_owned(),
+            TextEncoding::Latin1 => content.iter().map(|&b| b as char).collect(),
+            TextEncoding::Windows1252 => WINDOWS_1252.decode_without_bom_handling(content).0.into_owned(),
+            TextEncoding::ShiftJis => SHIFT_JIS.decode_without_bom_handling(content).0.into_owned()
+        }
+    }
+}
+
+fn encode_with(encoding: &'static encoding_rs::Encoding, text: &str) -> Option<Vec<u8>> {
+    let (bytes, _, unmappable) = encoding.encode(text);
+    if unmappable { None } else { Some(bytes.into_owned()) }
+}
+
+// Valid UTF-8 (including plain ASCII) is taken as UTF-8.
+// Otherwise the bytes are taken as Shift-JIS if they form valid double-byte characters that look like Japanese text,
+// since Latin-1 letters followed by ASCII letters can form valid Shift-JIS pairs too.
+// Everything else is Latin-1, or Windows-1252 if it uses 80-9F.
+pub fn detect_encoding(bytes: &[u8]) -> TextEncoding {
+    if std::str::from_utf8(bytes).is_ok() {
+        return TextEncoding::Utf8;
+    }
+
+    if looks_like_shift_jis(bytes) {
+        return TextEncoding::ShiftJis;
+    }
+
+    if bytes.iter().any(|b| (0x80..=0x9F).contains(b)) {
+        TextEncoding::Windows1252
+    } else {
+        TextEncoding::Latin1
+    }
+}
+
+fn looks_like_shift_jis(bytes: &[u8]) -> bool {
+    let mut pairs: usize = 0;
+    // Kana and full-width punctuation, lead bytes 81-83
+    let mut kana_pairs: usize = 0;
+    // Pairs with an ASCII trail byte, the ones a Latin-1 letter followed by an ASCII letter produces
+    let mut ascii_trail_pairs: usize = 0;
+
+    let mut i: usize = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            0x00..=0x7F | 0xA1..=0xDF => i += 1,
+            lead @ (0x81..=0x9F | 0xE0..=0xEF) => {
+                match bytes.get(i + 1) {
+                    Some(0x40..=0x7E) => ascii_trail_pairs += 1,
+                    Some(0x80..=0xFC) => {},
+                    _ => return false
+                }
+                pairs += 1;
+                if (0x81..=0x83).contains(&lead) {
+                    kana_pairs += 1;
+                }
+                i += 2;
+            },
+            // 80, A0, user-defined characters (F0-FC) and FD-FF
+            _ => return false
+        }
+    }
+
+    pairs > 0 && (kana_pairs > 0 || ascii_trail_pairs == 0)
+}
+
+impl From<Vec<u8>> for Text {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self::new(bytes)
+    }
+}
+
+impl From<&[u8]> for Text {
+    fn from(bytes: &[u8]) -> Self {
+        Self::new(bytes.to_vec())
+    }
+}
+
+impl From<String> for Text {
+    fn from(text: String) -> Self {
+        Self::new(text.into_bytes())
+    }
+}
+
+impl From<&str> for Text {
+    fn from(text: &str) -> Self {
+        Self::new(text.as_bytes().to_vec())
+    }
+}
+
+// Compares the bytes with the UTF-8 encoding of the string
+impl PartialEq<str> for Text {
+    fn eq(&self, other: &str) -> bool {
+        self.bytes == other.as_bytes()
+    }
+}
+
+impl PartialEq<&str> for Text {
+    fn eq(&self, other: &&str) -> bool {
+        self.bytes == other.as_bytes()
+    }
+}
+
+impl std::fmt::Display for Text {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.decode_lossy(self.detect_encoding()))
+    }
+}
+
+// The raw bytes, the bytes that aren't printable ASCII are escaped
+impl std::fmt::Debug for Text {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Text(b\"{}\")", self.bytes.escape_ascii())
+    }
+}
+
+// Decodes the text meta-events of a track in order.
+// Following RP-026, a tag applies to its own event and to all of the following ones until the next tag,
+// the events before the first tag are decoded with the detected encoding.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TextDecoder {
+    encoding: Option<TextEncoding>
+}
+
+impl TextDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // The encoding of the last tag
+    pub fn encoding(&self) -> Option<TextEncoding> {
+        self.encoding
+    }
+
+    pub fn decode(&mut self, text: &Text) -> String {
+        if let Some(tag) = text.tag() {
+            self.encoding = Some(tag);
+        }
+
+        text.decode_lossy(self.encoding.unwrap_or_else(|| detect_encoding(text.content())))
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        // 亡き王女 in Shift-JIS
+        let japanese = Text::new(vec![0x96, 0x53, 0x82, 0xAB, 0x89, 0xA4, 0x8F, 0x97]);
+        if japanese.decode(TextEncoding::ShiftJis).as_deref() != Some("亡き王女") || japanese.decode(TextEncoding::Utf8).is_some() {
+            panic!("1. fail: wrong Shift-JIS decoding");
+        }
+        // ① is one of the CP932 extensions
+        if Text::new(vec![0x87, 0x40]).decode(TextEncoding::ShiftJis).as_deref() != Some("①") {
+            panic!("1. fail: wrong CP932 decoding");
+        }
+
+        let latin = Text::new(vec![b'D', 0xE9, b'c', b'o', b'r', 0x80]);
+        if latin.decode(TextEncoding::Latin1).as_deref() != Some("D\u{E9}cor\u{80}") || latin.decode(TextEncoding::Windows1252).as_deref() != Some("Décor€") {
+            panic!("2. fail: wrong Latin-1 decoding");
+        }
+        if latin.decode_lossy(TextEncoding::Utf8) != "D\u{FFFD}cor\u{FFFD}" {
+            panic!("2. fail: wrong lossy decoding");
+        }
+
+        if Text::encode("亡き王女", TextEncoding::ShiftJis) != Some(japanese) || Text::encode("亡き王女", TextEncoding::Latin1).is_some() {
+            panic!("3. fail: wrong encoding");
+        }
+    }
+
+    #[test]
+    fn test_detect_encoding() {
+        for (bytes, expected) in [
+            (&b"Piano"[..], TextEncoding::Utf8),
+            ("Überspielung".as_bytes(), TextEncoding::Utf8),
+            // 亡き王女の為のセプテット
+            (&[0x96, 0x53, 0x82, 0xAB, 0x89, 0xA4, 0x8F, 0x97, 0x82, 0xCC, 0x88, 0xD7, 0x82, 0xCC, 0x83, 0x5A, 0x83, 0x76, 0x83, 0x65, 0x83, 0x62, 0x83, 0x67], TextEncoding::ShiftJis),
+            // 東方, kanji only
+            (&[0x93, 0x8C, 0x95, 0xFB], TextEncoding::ShiftJis),
+            // Décor, E9 63 is a valid Shift-JIS pair
+            (&[b'D', 0xE9, b'c', b'o', b'r'], TextEncoding::Latin1),
+            (&[b'C', b'a', b'f', 0xE9], TextEncoding::Latin1),
+            // “Hi”
+            (&[0x93, b'H', b'i', 0x94], TextEncoding::Windows1252)
+        ] {
+            if detect_encoding(bytes) != expected {
+                panic!("1. fail: {:X?} detected as {:?}", bytes, detect_encoding(bytes));
+            }
+        }
+
+        if Text::from("{@JP}Piano").detect_encoding() != TextEncoding::ShiftJis || Text::from("{@LATIN}Piano").content() != b"Piano" {
+            panic!("2. fail: wrong tag");
+        }
+
+        // Debug shows the bytes that were stored, not a decoding of them
+        let text = Text::new(vec![b'C', b'a', b'f', 0xE9, b'"']);
+        if format!("{:?}", text) != r#"Text(b"Caf\xe9\"")"# || format!("{}", text) != "Café\"" {
+            panic!("3. fail: wrong formatting {:?}", text);
+        }
+    }
+
+    #[test]
+    fn test_text_decoder() {
+        let texts = [
+            Text::from("Intro"),
+            Text::new([b"{@LATIN}".to_vec(), vec![b'D', 0xE9, b'j', 0xE0]].concat()),
+            // Would be detected as Shift-JIS without the tag
+            Text::new(vec![0x93, 0x8C, 0x95, 0xFB]),
+            Text::new([b"{@JP}".to_vec(), vec![0x93, 0x8C, 0x95, 0xFB]].concat())
+        ];
+
+        let mut decoder = TextDecoder::new();
+        let decoded: Vec<String> = texts.iter().map(|t| decoder.decode(t)).collect();
+        if decoded != vec!["Intro", "Déjà", "\u{93}\u{8C}\u{95}\u{FB}", "東方"] || decoder.encoding() != Some(TextEncoding::ShiftJis) {
+            panic!("fail: wrong texts {:?}", decoded);
+        }
+    }
+
+}
@@ -0,0 +1,198 @@
+// MIDI clocks (timing clock messages) per quarter note
+pub const MIDI_CLOCKS_PER_QUARTER_NOTE: u32 = 24;
+
+// The largest power of two exponent of a denominator that fits in a u32
+pub const MAX_DENOMINATOR_EXPONENT: u8 = 31;
+
+// The note value of a beat, e.g. a dotted quarter note in 6/8
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BeatUnit {
+    // 4 for a quarter note, 8 for an eighth note...
+    pub denominator: u32,
+    pub dotted: bool
+}
+
+impl BeatUnit {
+    // The length of the beat in MIDI clocks, fractional for notes shorter than a 64th
+    pub fn midi_clocks(self) -> f64 {
+        let clocks = (MIDI_CLOCKS_PER_QUARTER_NOTE * 4) as f64 / self.denominator as f64;
+        if self.dotted { clocks * 1.5 } else { clocks }
+    }
+}
+
+// A time signature as stored in the FF 58 meta-event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TimeSignature {
+    numerator: u8,
+    // The denominator is 2 to the power of this
+    denominator_exponent: u8,
+    midi_clocks_per_metronome_click: u8,
+    thirty_second_notes_per_midi_quarter_note: u8
+}
+
+impl TimeSignature {
+    pub const COMMON_TIME: Self = Self {
+        numerator: 4,
+        denominator_exponent: 2,
+        midi_clocks_per_metronome_click: 24,
+        thirty_second_notes_per_midi_quarter_note: 8
+    };
+
+    // `None` if the numerator is 0 or the denominator isn't a power of two.
+    // The metronome clicks once per beat.
+    pub fn new(numerator: u8, denominator: u32) -> Option<Self> {
+        if numerator == 0 || !denominator.is_power_of_two() {
+            return None;
+        }
+
+        let mut time_signature = Self {
+            numerator,
+            denominator_exponent: denominator.trailing_zeros() as u8,
+            midi_clocks_per_metronome_click: 0,
+            thirty_second_notes_per_midi_quarter_note: 8
+        };
+        time_signature.midi_clocks_per_metronome_click = time_signature.beat_unit().midi_clocks().round().clamp(1.0, 255.0) as u8;
+
+        Some(time_signature)
+    }
+
+    // From the four data bytes of the meta-event, `None` if the numerator is 0 or the denominator exponent is too large
+    pub fn from_raw(numerator: u8, denominator_exponent: u8, midi_clocks_per_metronome_click: u8, thirty_second_notes_per_midi_quarter_note: u8) -> Option<Self> {
+        if numerator == 0 || denominator_exponent > MAX_DENOMINATOR_EXPONENT {
+            return None;
+        }
+
+        Some(Self { numerator, denominator_exponent, midi_clocks_per_metronome_click, thirty_second_notes_per_midi_quarter_note })
+    }
+
+    // (nn, dd, cc, bb)
+    pub fn to_raw(self) -> (u8, u8, u8, u8) {
+        (self.numerator, self.denominator_exponent, self.midi_clocks_per_metronome_click, self.thirty_second_notes_per_midi_quarter_note)
+    }
+
+    pub fn numerator(self) -> u8 {
+        self.numerator
+    }
+
+    pub fn denominator(self) -> u32 {
+        1 << self.denominator_exponent
+    }
+
+    pub fn denominator_exponent(self) -> u8 {
+        self.denominator_exponent
+    }
+
+    pub fn midi_clocks_per_metronome_click(self) -> u8 {
+        self.midi_clocks_per_metronome_click
+    }
+
+    // 8 unless the file redefines the length of a quarter note
+    pub fn thirty_second_notes_per_midi_quarter_note(self) -> u8 {
+        self.thirty_second_notes_per_midi_quarter_note
+    }
+
+    // 6/8, 9/8, 12/16... group the notes by three, 3/4 and 3/8 are simple triple meters
+    pub fn is_compound(self) -> bool {
+        self.numerator > 3 && self.numerator.is_multiple_of(3) && self.denominator_exponent > 0
+    }
+
+    pub fn is_simple(self) -> bool {
+        !self.is_compound()
+    }
+
+    pub fn beats_per_bar(self) -> u32 {
+        if self.is_compound() { self.numerator as u32 / 3 } else { self.numerator as u32 }
+    }
+
+    pub fn beat_unit(self) -> BeatUnit {
+        if self.is_compound() {
+            BeatUnit { denominator: self.denominator() / 2, dotted: true }
+        } else {
+            BeatUnit { denominator: self.denominator(), dotted: false }
+        }
+    }
+
+    // How many times the metronome clicks during a beat, `None` if it never clicks
+    pub fn clicks_per_beat(self) -> Option<f64> {
+        if self.midi_clocks_per_metronome_click == 0 {
+            return None;
+        }
+
+        Some(self.beat_unit().midi_clocks() / self.midi_clocks_per_metronome_click as f64)
+    }
+
+    // The lengths are rounded down to whole ticks, but never below 1
+    pub fn ticks_per_bar(self, ticks_per_quarter_note: u16) -> u64 {
+        ((self.numerator as u64 * ticks_per_quarter_note as u64 * 4) >> self.denominator_exponent).max(1)
+    }
+
+    pub fn ticks_per_beat(self, ticks_per_quarter_note: u16) -> u64 {
+        let notes_per_beat: u64 = if self.is_compound() { 3 } else { 1 };
+        ((notes_per_beat * ticks_per_quarter_note as u64 * 4) >> self.denominator_exponent).max(1)
+    }
+}
+
+impl Default for TimeSignature {
+    fn default() -> Self {
+        Self::COMMON_TIME
+    }
+}
+
+impl std::fmt::Display for TimeSignature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.numerator, self.denominator())
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    #[test]
+    fn test_meters() {
+        // 6/8 with a click on every dotted quarter
+        let compound = TimeSignature::from_raw(6, 3, 36, 8).unwrap();
+        if compound.denominator() != 8 || !compound.is_compound() || compound.beats_per_bar() != 2
+            || compound.beat_unit() != (BeatUnit { denominator: 4, dotted: true }) || compound.clicks_per_beat() != Some(1.0) {
+            panic!("1. fail: wrong 6/8");
+        }
+        if compound.ticks_per_bar(96) != 288 || compound.ticks_per_beat(96) != 144 || compound.to_string() != "6/8" {
+            panic!("1. fail: wrong 6/8 ticks");
+        }
+
+        // 3/4 clicking on every eighth note
+        let simple = TimeSignature::from_raw(3, 2, 12, 8).unwrap();
+        if simple.is_compound() || simple.beats_per_bar() != 3 || simple.beat_unit() != (BeatUnit { denominator: 4, dotted: false }) || simple.clicks_per_beat() != Some(2.0) {
+            panic!("2. fail: wrong 3/4");
+        }
+
+        // 7/8 and 12/16
+        let odd = TimeSignature::new(7, 8).unwrap();
+        if odd.is_compound() || odd.beats_per_bar() != 7 || odd.midi_clocks_per_metronome_click() != 12 || odd.ticks_per_bar(96) != 336 {
+            panic!("3. fail: wrong 7/8");
+        }
+        let sixteenths = TimeSignature::new(12, 16).unwrap();
+        if sixteenths.beats_per_bar() != 4 || sixteenths.ticks_per_beat(96) != 72 || sixteenths.midi_clocks_per_metronome_click() != 18 {
+            panic!("3. fail: wrong 12/16");
+        }
+
+        if TimeSignature::new(4, 4) != Some(TimeSignature::COMMON_TIME) || TimeSignature::COMMON_TIME.to_raw() != (4, 2, 24, 8) {
+            panic!("4. fail: wrong 4/4");
+        }
+    }
+
+    #[test]
+    fn test_validation() {
+        if TimeSignature::from_raw(0, 2, 24, 8).is_some() || TimeSignature::from_raw(4, 32, 24, 8).is_some() {
+            panic!("1. fail: invalid time signature accepted");
+        }
+        if TimeSignature::new(4, 6).is_some() || TimeSignature::new(0, 4).is_some() {
+            panic!("2. fail: invalid time signature accepted");
+        }
+        if TimeSignature::from_raw(4, 2, 0, 8).unwrap().clicks_per_beat().is_some() {
+            panic!("3. fail: no metronome");
+        }
+    }
+
+}
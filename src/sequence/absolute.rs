@@ -0,0 +1,164 @@
+use crate::parser::chunk;
+use crate::timing::tempo_map;
+use crate::writer;
+
+// Yields the events of a track together with their absolute tick, counted from the start of the track.
+#[derive(Debug, Clone)]
+pub struct AbsoluteEvents<'a> {
+    events: std::slice::Iter<'a, chunk::TrackEvent>,
+    tick: u64
+}
+
+impl<'a> AbsoluteEvents<'a> {
+    pub fn new(events: &'a [chunk::TrackEvent]) -> Self {
+        Self { events: events.iter(), tick: 0 }
+    }
+
+    // Adds the time of every event in seconds.
+    // For format 2 files use the tempo map of the track (`TempoMap::for_track`).
+    pub fn with_seconds(self, tempo_map: &'a tempo_map::TempoMap) -> TimedEvents<'a> {
+        TimedEvents { events: self, tempo_map }
+    }
+}
+
+impl<'a> Iterator for AbsoluteEvents<'a> {
+    type Item = (u64, &'a chunk::TrackEvent);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let event = self.events.next()?;
+        self.tick += event.delta_time as u64;
+        Some((self.tick, event))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.events.size_hint()
+    }
+}
+
+impl ExactSizeIterator for AbsoluteEvents<'_> {}
+
+// Yields (absolute tick, seconds, event)
+#[derive(Debug, Clone)]
+pub struct TimedEvents<'a> {
+    events: AbsoluteEvents<'a>,
+    tempo_map: &'a tempo_map::TempoMap
+}
+
+impl<'a> Iterator for TimedEvents<'a> {
+    type Item = (u64, f64, &'a chunk::TrackEvent);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (tick, event) = self.events.next()?;
+        Some((tick, self.tempo_map.ticks_to_seconds(tick), event))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.events.size_hint()
+    }
+}
+
+impl ExactSizeIterator for TimedEvents<'_> {}
+
+pub fn absolute_events(events: &[chunk::TrackEvent]) -> AbsoluteEvents<'_> {
+    AbsoluteEvents::new(events)
+}
+
+// Two consecutive events further apart than a variable-length quantity can store
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeltaTimeOverflowError {
+    pub last_tick: u64,
+    pub tick: u64
+}
+
+impl std::fmt::Display for DeltaTimeOverflowError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The delta-time {} between ticks {} and {} doesn't fit in a variable-length quantity", self.tick - self.last_tick, self.last_tick, self.tick)
+    }
+}
+
+impl std::error::Error for DeltaTimeOverflowError {}
+
+// The inverse of `absolute_events`, turns (absolute tick, event) pairs into delta-encoded track events.
+// The events are sorted by tick, events at the same tick keep their order.
+pub fn to_delta_events<I: IntoIterator<Item=(u64, chunk::TrackEventType)>>(events: I) -> Result<Vec<chunk::TrackEvent>, DeltaTimeOverflowError> {
+    let mut events: Vec<(u64, chunk::TrackEventType)> = events.into_iter().collect();
+    events.sort_by_key(|(tick, _)| *tick);
+
+    let mut res = Vec::<chunk::TrackEvent>::with_capacity(events.len());
+    let mut last_tick: u64 = 0;
+
+    for (tick, event) in events {
+        let delta_time = tick - last_tick;
+        if delta_time > writer::MAX_VARIABLE_LENGTH as u64 {
+            return Err(DeltaTimeOverflowError { last_tick, tick });
+        }
+
+        res.push(chunk::TrackEvent {
+            delta_time: delta_time as u32,
+            event,
+            encoding: None
+        });
+        last_tick = tick;
+    }
+
+    Ok(res)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::parser::{midi_event, meta_event};
+
+    fn note_on(key: u8) -> chunk::TrackEventType {
+        chunk::TrackEventType::Midi(midi_event::MidiEvent::NoteOn { channel: 0, key, velocity: 100 })
+    }
+
+    #[test]
+    fn test_absolute_events() {
+        let events = to_delta_events(vec![
+            (96, note_on(62)),
+            (0, note_on(60)),
+            (96, note_on(64)),
+            (192, chunk::TrackEventType::Meta(meta_event::MetaEvent::EndOfTrack))
+        ]).unwrap();
+
+        let deltas: Vec<u32> = events.iter().map(|e| e.delta_time).collect();
+        if deltas != vec![0, 96, 0, 96] || events[1].event != note_on(62) || events[2].event != note_on(64) {
+            panic!("1. fail: wrong delta-times {:?}", deltas);
+        }
+
+        let ticks: Vec<u64> = absolute_events(&events).map(|(tick, _)| tick).collect();
+        if ticks != vec![0, 96, 96, 192] {
+            panic!("2. fail: wrong ticks {:?}", ticks);
+        }
+
+        let tempo_map = tempo_map::TempoMap::new(chunk::Division::TicksPerQuarterNote(96), &[(96, 1000000)], 192);
+        let seconds: Vec<f64> = absolute_events(&events).with_seconds(&tempo_map).map(|(_, s, _)| s).collect();
+        if seconds != vec![0.0, 0.5, 0.5, 1.5] {
+            panic!("3. fail: wrong seconds {:?}", seconds);
+        }
+
+        if to_delta_events(vec![(0, note_on(60)), (0x10000000, note_on(60))]) != Err(DeltaTimeOverflowError { last_tick: 0, tick: 0x10000000 }) {
+            panic!("4. fail: delta-time too long");
+        }
+    }
+
+    #[test]
+    fn test_round_trip_test_midis() {
+        for (name, data) in crate::parser::read_test_midis() {
+            let midi_file = match crate::parser::parse_midi_file(&data) {
+                Ok(f) => f,
+                Err(e) => panic!("{name}: {e}")
+            };
+
+            for track in &midi_file.tracks {
+                let res = to_delta_events(absolute_events(track.events()).map(|(tick, e)| (tick, e.event.clone()))).unwrap();
+                if res != track.events() {
+                    panic!("{name}: round trip failed");
+                }
+            }
+        }
+    }
+
+}
@@ -0,0 +1,160 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use crate::parser::{chunk, midi_event};
+
+// The order of the events at the same tick, coming from different tracks.
+fn event_priority(event: &chunk::TrackEvent) -> u8 {
+    use midi_event::MidiEvent::*;
+
+    match &event.event {
+        chunk::TrackEventType::Meta(_) => 0,
+        chunk::TrackEventType::Midi(NoteOff { .. } | NoteOn { velocity: 0, .. }) => 1,
+        chunk::TrackEventType::Midi(NoteOn { .. }) => 3,
+        chunk::TrackEventType::Midi(_) => 2
+    }
+}
+
+// Interleaves the events of all of the tracks in time order, yielding (absolute tick, track index, event).
+//
+// Events at the same tick are ordered by:
+//  1. meta-events,
+//  2. note-offs (including NoteOn with velocity 0),
+//  3. the other MIDI events (controllers, program changes, System Exclusive, ...),
+//  4. note-ons,
+// and then by the track index.
+// The events of a single track always keep their order, the rules above only apply between the tracks.
+#[derive(Debug, Clone)]
+pub struct MergedEvents<'a> {
+    cursors: Vec<std::slice::Iter<'a, chunk::TrackEvent>>,
+    // (tick, priority, track index) of the next event of every track that has one
+    heads: BinaryHeap<Reverse<(u64, u8, usize)>>
+}
+
+impl<'a> MergedEvents<'a> {
+    pub fn new(tracks: &'a [chunk::Track]) -> Self {
+        let mut cursors = Vec::<std::slice::Iter<'a, chunk::TrackEvent>>::with_capacity(tracks.len());
+        let mut heads = BinaryHeap::<Reverse<(u64, u8, usize)>>::with_capacity(tracks.len());
+
+        for (track_index, track) in tracks.iter().enumerate() {
+            let events = track.events();
+
+            if let Some(event) = events.first() {
+                heads.push(Reverse((event.delta_time as u64, event_priority(event), track_index)));
+            }
+            cursors.push(events.iter());
+        }
+
+        Self { cursors, heads }
+    }
+}
+
+impl<'a> Iterator for MergedEvents<'a> {
+    type Item = (u64, usize, &'a chunk::TrackEvent);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let Reverse((tick, _, track_index)) = self.heads.pop()?;
+
+        let cursor = &mut self.cursors[track_index];
+        let event = cursor.next()?;
+
+        if let Some(next) = cursor.as_slice().first() {
+            self.heads.push(Reverse((tick + next.delta_time as u64, event_priority(next), track_index)));
+        }
+
+        Some((tick, track_index, event))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.cursors.iter().map(|c| c.len()).sum();
+        (remaining, Some(remaining))
+    }
+}
+
+impl ExactSizeIterator for MergedEvents<'_> {}
+
+pub fn merged_events(midi_file: &chunk::MidiFile) -> MergedEvents<'_> {
+    MergedEvents::new(&midi_file.tracks)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::parser::meta_event;
+
+    fn midi(delta_time: u32, event: midi_event::MidiEvent) -> chunk::TrackEvent {
+        chunk::TrackEvent { delta_time, event: chunk::TrackEventType::Midi(event), encoding: None }
+    }
+
+    fn meta(delta_time: u32, event: meta_event::MetaEvent) -> chunk::TrackEvent {
+        chunk::TrackEvent { delta_time, event: chunk::TrackEventType::Meta(event), encoding: None }
+    }
+
+    #[test]
+    fn test_merged_events() {
+        use midi_event::MidiEvent::*;
+
+        let tracks = vec![
+            chunk::Track::new(vec![
+                midi(0, NoteOn { channel: 0, key: 60, velocity: 100 }),
+                midi(96, NoteOn { channel: 0, key: 62, velocity: 100 }),
+                meta(96, meta_event::MetaEvent::EndOfTrack)
+            ]),
+            chunk::Track::new(vec![
+                meta(0, meta_event::MetaEvent::SetTempo { microseconds_per_midi_quarter_note: 400000 }),
+                midi(96, NoteOn { channel: 1, key: 60, velocity: 0 }),
+                midi(0, ProgramChange { channel: 1, new_program_number: 5 }),
+                meta(192, meta_event::MetaEvent::EndOfTrack)
+            ]),
+            chunk::Track::new(vec![
+                midi(96, NoteOff { channel: 2, key: 60, velocity: 0 }),
+                meta(0, meta_event::MetaEvent::EndOfTrack)
+            ])
+        ];
+
+        let merged = MergedEvents::new(&tracks);
+        if merged.len() != 9 {
+            panic!("1. fail: wrong length {}", merged.len());
+        }
+
+        let order: Vec<(u64, usize)> = merged.map(|(tick, track_index, _)| (tick, track_index)).collect();
+        let expected = vec![
+            (0, 1), (0, 0),
+            // The note-offs of tracks 1 and 2, the end of track 2 becomes the next event with the highest priority,
+            // then the program change of track 1 and the note-on of track 0
+            (96, 1), (96, 2), (96, 2), (96, 1), (96, 0),
+            (192, 0),
+            (288, 1)
+        ];
+        if order != expected {
+            panic!("2. fail: wrong order {:?}", order);
+        }
+    }
+
+    #[test]
+    fn test_merged_test_midis() {
+        for (name, data) in crate::parser::read_test_midis() {
+            let midi_file = match crate::parser::parse_midi_file(&data) {
+                Ok(f) => f,
+                Err(e) => panic!("{name}: {e}")
+            };
+
+            let total: usize = midi_file.tracks.iter().map(|t| t.events().len()).sum();
+
+            let mut count: usize = 0;
+            let mut last_tick: u64 = 0;
+            for (tick, _, _) in merged_events(&midi_file) {
+                if tick < last_tick {
+                    panic!("{name}: events out of order");
+                }
+                last_tick = tick;
+                count += 1;
+            }
+            if count != total {
+                panic!("{name}: {count} events instead of {total}");
+            }
+        }
+    }
+
+}
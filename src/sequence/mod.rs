@@ -0,0 +1,3 @@
+pub mod absolute;
+pub mod merge;
+pub mod notes;
@@ -0,0 +1,257 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::parser::{chunk, midi_event, meta_event};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Note {
+    pub channel: u8,
+    pub key: u8,
+    pub start_tick: u64,
+    pub duration_ticks: u64,
+    pub velocity: u8,
+    // 0 for notes ended with a NoteOn with velocity 0 or left open until the end of the track
+    pub off_velocity: u8,
+    pub track: usize
+}
+
+impl Note {
+    pub fn end_tick(&self) -> u64 {
+        self.start_tick + self.duration_ticks
+    }
+}
+
+// Which note a note-off ends when the same key is played again before being released.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OverlapMode {
+    // The earliest note still playing
+    #[default]
+    FirstInFirstOut,
+    // The latest note
+    LastInFirstOut
+}
+
+// What happens to the notes still playing at the end of the track.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum UnterminatedNoteMode {
+    // They end at the EndOfTrack event (or at the last event, if there's none)
+    #[default]
+    EndAtEndOfTrack,
+    Drop
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct NoteOptions {
+    pub overlap: OverlapMode,
+    pub unterminated: UnterminatedNoteMode
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NoteDiagnostic {
+    // A note-off with no matching note-on
+    OrphanNoteOff {
+        track: usize,
+        tick: u64,
+        channel: u8,
+        key: u8
+    },
+    // A note-on never followed by a note-off, handled according to `UnterminatedNoteMode`
+    UnterminatedNote {
+        track: usize,
+        start_tick: u64,
+        channel: u8,
+        key: u8
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Notes {
+    // Sorted by start tick, notes starting at the same tick keep the order of the tracks
+    pub notes: Vec<Note>,
+    pub diagnostics: Vec<NoteDiagnostic>
+}
+
+// Pairs the note-ons and note-offs of a single track, the ticks are counted from the start of the track.
+pub fn extract_track_notes(events: &[chunk::TrackEvent], track: usize, options: &NoteOptions) -> Notes {
+    use midi_event::MidiEvent::*;
+
+    let mut res = Notes::default();
+    // (start tick, velocity) of the notes playing, by (channel, key)
+    let mut playing = HashMap::<(u8, u8), VecDeque<(u64, u8)>>::new();
+    let mut tick: u64 = 0;
+
+    for event in events {
+        tick += event.delta_time as u64;
+
+        let (channel, key, off_velocity) = match &event.event {
+            chunk::TrackEventType::Midi(NoteOn { channel, key, velocity }) if *velocity != 0 => {
+                playing.entry((*channel, *key)).or_default().push_back((tick, *velocity));
+                continue;
+            },
+            chunk::TrackEventType::Midi(NoteOn { channel, key, .. }) => (*channel, *key, 0),
+            chunk::TrackEventType::Midi(NoteOff { channel, key, velocity }) => (*channel, *key, *velocity),
+            chunk::TrackEventType::Meta(meta_event::MetaEvent::EndOfTrack) => break,
+            _ => continue
+        };
+
+        let started = playing.get_mut(&(channel, key)).and_then(|notes| match options.overlap {
+            OverlapMode::FirstInFirstOut => notes.pop_front(),
+            OverlapMode::LastInFirstOut => notes.pop_back()
+        });
+
+        match started {
+            Some((start_tick, velocity)) => res.notes.push(Note {
+                channel,
+                key,
+                start_tick,
+                duration_ticks: tick - start_tick,
+                velocity,
+                off_velocity,
+                track
+            }),
+            None => res.diagnostics.push(NoteDiagnostic::OrphanNoteOff { track, tick, channel, key })
+        }
+    }
+
+    let mut unterminated: Vec<((u8, u8), (u64, u8))> = playing.into_iter()
+        .flat_map(|(k, notes)| notes.into_iter().map(move |n| (k, n)))
+        .collect();
+    unterminated.sort_by_key(|((channel, key), (start_tick, _))| (*start_tick, *channel, *key));
+
+    for ((channel, key), (start_tick, velocity)) in unterminated {
+        res.diagnostics.push(NoteDiagnostic::UnterminatedNote { track, start_tick, channel, key });
+
+        if options.unterminated == UnterminatedNoteMode::EndAtEndOfTrack {
+            res.notes.push(Note {
+                channel,
+                key,
+                start_tick,
+                duration_ticks: tick - start_tick,
+                velocity,
+                off_velocity: 0,
+                track
+            });
+        }
+    }
+
+    res.notes.sort_by_key(|n| n.start_tick);
+    res
+}
+
+// The notes of all of the tracks.
+// In format 2 files the ticks of every note are counted from the start of its own track.
+pub fn extract_notes(midi_file: &chunk::MidiFile, options: &NoteOptions) -> Notes {
+    let mut res = Notes::default();
+
+    for (track_index, track) in midi_file.tracks.iter().enumerate() {
+        let track_notes = extract_track_notes(track.events(), track_index, options);
+        res.notes.extend(track_notes.notes);
+        res.diagnostics.extend(track_notes.diagnostics);
+    }
+
+    res.notes.sort_by_key(|n| n.start_tick);
+    res
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn midi(delta_time: u32, event: midi_event::MidiEvent) -> chunk::TrackEvent {
+        chunk::TrackEvent { delta_time, event: chunk::TrackEventType::Midi(event), encoding: None }
+    }
+
+    fn end_of_track(delta_time: u32) -> chunk::TrackEvent {
+        chunk::TrackEvent { delta_time, event: chunk::TrackEventType::Meta(meta_event::MetaEvent::EndOfTrack), encoding: None }
+    }
+
+    #[test]
+    fn test_notes() {
+        use midi_event::MidiEvent::*;
+
+        let events = vec![
+            midi(0, NoteOn { channel: 0, key: 60, velocity: 100 }),
+            midi(48, NoteOn { channel: 1, key: 60, velocity: 90 }),
+            midi(48, NoteOn { channel: 0, key: 60, velocity: 0 }),
+            midi(0, NoteOff { channel: 1, key: 60, velocity: 40 }),
+            midi(0, NoteOff { channel: 0, key: 62, velocity: 0 })
+        ];
+
+        let res = extract_track_notes(&events, 3, &NoteOptions::default());
+        let expected = vec![
+            Note { channel: 0, key: 60, start_tick: 0, duration_ticks: 96, velocity: 100, off_velocity: 0, track: 3 },
+            Note { channel: 1, key: 60, start_tick: 48, duration_ticks: 48, velocity: 90, off_velocity: 40, track: 3 }
+        ];
+        if res.notes != expected {
+            panic!("1. fail: wrong notes {:?}", res.notes);
+        }
+        if res.diagnostics != vec![NoteDiagnostic::OrphanNoteOff { track: 3, tick: 96, channel: 0, key: 62 }] {
+            panic!("1. fail: wrong diagnostics {:?}", res.diagnostics);
+        }
+    }
+
+    #[test]
+    fn test_overlapping_notes() {
+        use midi_event::MidiEvent::*;
+
+        let events = vec![
+            midi(0, NoteOn { channel: 0, key: 60, velocity: 100 }),
+            midi(10, NoteOn { channel: 0, key: 60, velocity: 50 }),
+            midi(10, NoteOff { channel: 0, key: 60, velocity: 0 }),
+            midi(10, NoteOff { channel: 0, key: 60, velocity: 0 })
+        ];
+
+        let fifo = extract_track_notes(&events, 0, &NoteOptions { overlap: OverlapMode::FirstInFirstOut, ..Default::default() });
+        let durations: Vec<(u8, u64)> = fifo.notes.iter().map(|n| (n.velocity, n.duration_ticks)).collect();
+        if durations != vec![(100, 20), (50, 20)] {
+            panic!("1. fail: wrong notes {:?}", durations);
+        }
+
+        let lifo = extract_track_notes(&events, 0, &NoteOptions { overlap: OverlapMode::LastInFirstOut, ..Default::default() });
+        let durations: Vec<(u8, u64)> = lifo.notes.iter().map(|n| (n.velocity, n.duration_ticks)).collect();
+        if durations != vec![(100, 30), (50, 10)] {
+            panic!("2. fail: wrong notes {:?}", durations);
+        }
+    }
+
+    #[test]
+    fn test_unterminated_notes() {
+        use midi_event::MidiEvent::*;
+
+        let events = vec![
+            midi(0, NoteOn { channel: 0, key: 60, velocity: 100 }),
+            midi(10, NoteOn { channel: 0, key: 64, velocity: 100 }),
+            end_of_track(86)
+        ];
+
+        let res = extract_track_notes(&events, 0, &NoteOptions::default());
+        let durations: Vec<(u8, u64)> = res.notes.iter().map(|n| (n.key, n.duration_ticks)).collect();
+        if durations != vec![(60, 96), (64, 86)] || res.diagnostics.len() != 2 {
+            panic!("1. fail: wrong notes {:?}", durations);
+        }
+
+        let res = extract_track_notes(&events, 0, &NoteOptions { unterminated: UnterminatedNoteMode::Drop, ..Default::default() });
+        if !res.notes.is_empty() || res.diagnostics != vec![
+            NoteDiagnostic::UnterminatedNote { track: 0, start_tick: 0, channel: 0, key: 60 },
+            NoteDiagnostic::UnterminatedNote { track: 0, start_tick: 10, channel: 0, key: 64 }
+        ] {
+            panic!("2. fail: wrong diagnostics {:?}", res.diagnostics);
+        }
+    }
+
+    #[test]
+    fn test_test_midis() {
+        for (name, data) in crate::parser::read_test_midis() {
+            let midi_file = match crate::parser::parse_midi_file(&data) {
+                Ok(f) => f,
+                Err(e) => panic!("{name}: {e}")
+            };
+
+            let res = extract_notes(&midi_file, &NoteOptions::default());
+            if res.notes.is_empty() || !res.notes.windows(2).all(|w| w[0].start_tick <= w[1].start_tick) {
+                panic!("{name}: wrong notes");
+            }
+        }
+    }
+
+}
@@ -0,0 +1,4 @@
+pub mod musical_time;
+pub mod tempo_map;
+pub mod time_signature_map;
+pub mod timecode;
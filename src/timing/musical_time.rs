@@ -0,0 +1,382 @@
+use std::str::FromStr;
+
+use super::time_signature_map::TimeSignatureMap;
+
+// A position in bars and beats, written as "bar.beat.tick" like in most sequencers.
+// Bars and beats count from 1, the ticks from 0. The beats are the beats of the meter (see `TimeSignatureMap`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MusicalTime {
+    pub bar: u64,
+    pub beat: u32,
+    pub tick: u64
+}
+
+impl MusicalTime {
+    pub const START: Self = Self { bar: 1, beat: 1, tick: 0 };
+
+    pub fn new(bar: u64, beat: u32, tick: u64) -> Self {
+        Self { bar, beat, tick }
+    }
+
+    // `None` if the bar, counted from 1, doesn't fit in a u64
+    pub fn from_ticks(tick: u64, map: &TimeSignatureMap) -> Option<Self> {
+        let (bar, beat, tick) = map.ticks_to_bar_beat_tick(tick);
+        Some(Self { bar: bar.checked_add(1)?, beat: beat + 1, tick })
+    }
+
+    // Beats and ticks past the end of the bar carry on into the next bars, a bar or beat of 0 counts as 1.
+    // `None` if the position is past the last tick that fits in a u64.
+    pub fn to_ticks(self, map: &TimeSignatureMap) -> Option<u64> {
+        map.bar_beat_tick_to_ticks(self.bar.saturating_sub(1), self.beat.saturating_sub(1), self.tick)
+    }
+
+    // The same beat and tick, `bars` later (or earlier if negative), never before the first bar
+    pub fn add_bars(self, bars: i64) -> Self {
+        let bar = self.bar.max(1).saturating_add_signed(bars).max(1);
+        Self { bar, ..self }
+    }
+
+    // The position that the beat and tick of a shorter bar point to, e.g. 2.4.0 is 3.1.0 in 3/4
+    pub fn normalize(self, map: &TimeSignatureMap) -> Option<Self> {
+        Self::from_ticks(self.to_ticks(map)?, map)
+    }
+
+    // The start of the nearest beat, halfway between two beats rounds up
+    pub fn snap_to_beat(self, map: &TimeSignatureMap) -> Option<Self> {
+        let tick = self.to_ticks(map)?;
+        let (bar, beat, offset) = map.ticks_to_bar_beat_tick(tick);
+        let beat_start = tick - offset;
+        let next_beat_start = beat_start_tick(map, bar, beat + 1).or_else(|| bar.checked_add(1).and_then(|next| map.bar_start_tick(next)));
+
+        // The last beat before the overflow has no next beat to round up to
+        let snapped = match next_beat_start {
+            Some(next) if offset * 2 >= next - beat_start => next,
+            _ => beat_start
+        };
+        Self::from_ticks(snapped, map)
+    }
+}
+
+// The start of a beat (from 0), `None` past the last beat of the bar, past the start of a shorter bar's next one
+// or past the last tick that fits in a u64
+fn beat_start_tick(map: &TimeSignatureMap, bar: u64, beat: u32) -> Option<u64> {
+    let time_signature = map.time_signature_of_bar(bar);
+    if beat >= time_signature.beats_per_bar() {
+        return None;
+    }
+
+    let tick = map.bar_start_tick(bar)?.checked_add(beat as u64 * time_signature.ticks_per_beat(map.ticks_per_quarter_note()))?;
+    if bar.checked_add(1).and_then(|next| map.bar_start_tick(next)).is_some_and(|next| tick >= next) {
+        return None;
+    }
+
+    Some(tick)
+}
+
+impl Default for MusicalTime {
+    fn default() -> Self {
+        Self::START
+    }
+}
+
+impl std::fmt::Display for MusicalTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}.{}", self.bar, self.beat, self.tick)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MusicalTimeParseError {
+    // Not 1 to 3 numbers separated by '.' or ':'
+    InvalidFormat,
+    InvalidNumber(std::num::ParseIntError),
+    // Bars and beats count from 1
+    ZeroBarOrBeat
+}
+
+impl std::fmt::Display for MusicalTimeParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidFormat => write!(f, "Expected a position like \"12.3.240\" (bar.beat.tick)"),
+            Self::InvalidNumber(e) => write!(f, "Invalid number in the position: {}", e),
+            Self::ZeroBarOrBeat => write!(f, "Bars and beats count from 1")
+        }
+    }
+}
+
+impl std::error::Error for MusicalTimeParseError {}
+
+impl From<std::num::ParseIntError> for MusicalTimeParseError {
+    fn from(e: std::num::ParseIntError) -> Self {
+        Self::InvalidNumber(e)
+    }
+}
+
+// "12.3.240", "12:3:240", or shorter forms like "12.3" and "12", where the missing parts are at the start of the bar or beat
+impl FromStr for MusicalTime {
+    type Err = MusicalTimeParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.trim().split(['.', ':']).collect();
+        if parts.len() > 3 {
+            return Err(MusicalTimeParseError::InvalidFormat);
+        }
+
+        let bar: u64 = parts[0].parse()?;
+        let beat: u32 = match parts.get(1) {
+            Some(b) => b.parse()?,
+            None => 1
+        };
+        let tick: u64 = match parts.get(2) {
+            Some(t) => t.parse()?,
+            None => 0
+        };
+
+        if bar == 0 || beat == 0 {
+            return Err(MusicalTimeParseError::ZeroBarOrBeat);
+        }
+
+        Ok(Self { bar, beat, tick })
+    }
+}
+
+// Yields (tick, position) of the start of every bar, up to and including `end_tick`.
+// Stops early when the position no longer fits in a u64.
+#[derive(Debug, Clone)]
+pub struct BarLines<'a> {
+    map: &'a TimeSignatureMap,
+    // Counted from 0
+    bar: u64,
+    end_tick: u64,
+    done: bool
+}
+
+impl Iterator for BarLines<'_> {
+    type Item = (u64, MusicalTime);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let tick = self.map.bar_start_tick(self.bar);
+        let (Some(tick), Some(bar)) = (tick.filter(|&t| t <= self.end_tick), self.bar.checked_add(1)) else {
+            self.done = true;
+            return None;
+        };
+
+        let position = MusicalTime { bar, beat: 1, tick: 0 };
+        self.bar = bar;
+        Some((tick, position))
+    }
+}
+
+// Yields (tick, position) of the start of every beat, up to and including `end_tick`.
+// Stops early when the position no longer fits in a u64.
+#[derive(Debug, Clone)]
+pub struct BeatGrid<'a> {
+    map: &'a TimeSignatureMap,
+    // Counted from 0
+    bar: u64,
+    beat: u32,
+    end_tick: u64,
+    done: bool
+}
+
+impl Iterator for BeatGrid<'_> {
+    type Item = (u64, MusicalTime);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let tick = match beat_start_tick(self.map, self.bar, self.beat) {
+            Some(t) => Some(t),
+            None => self.bar.checked_add(1).and_then(|next| {
+                self.bar = next;
+                self.beat = 0;
+                self.map.bar_start_tick(next)
+            })
+        };
+        let (Some(tick), Some(bar)) = (tick.filter(|&t| t <= self.end_tick), self.bar.checked_add(1)) else {
+            self.done = true;
+            return None;
+        };
+
+        let position = MusicalTime { bar, beat: self.beat + 1, tick: 0 };
+        self.beat += 1;
+        Some((tick, position))
+    }
+}
+
+// For the whole song, pass `TempoMap::end_tick` as the end.
+pub fn bar_lines(map: &TimeSignatureMap, end_tick: u64) -> BarLines<'_> {
+    BarLines { map, bar: 0, end_tick, done: false }
+}
+
+pub fn beat_grid(map: &TimeSignatureMap, end_tick: u64) -> BeatGrid<'_> {
+    BeatGrid { map, bar: 0, beat: 0, end_tick, done: false }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::parser::time_signature::TimeSignature;
+
+    // 2 bars of 4/4, 2 bars of 6/8, then 3/4 - 96 ticks per quarter note
+    fn map() -> TimeSignatureMap {
+        TimeSignatureMap::new(96, &[(768, TimeSignature::new(6, 8).unwrap()), (1344, TimeSignature::new(3, 4).unwrap())])
+    }
+
+    #[test]
+    fn test_conversion() {
+        let map = map();
+
+        for (tick, text) in [(0, "1.1.0"), (500, "2.2.20"), (1000, "3.2.88"), (1344, "5.1.0"), (1344 + 288 * 10 + 200, "15.3.8")] {
+            let position = MusicalTime::from_ticks(tick, &map).unwrap();
+            if position.to_string() != text || position.to_ticks(&map) != Some(tick) {
+                panic!("1. fail: tick {} at {}", tick, position);
+            }
+            if text.parse::<MusicalTime>() != Ok(position) {
+                panic!("1. fail: {} parsed as {:?}", text, text.parse::<MusicalTime>());
+            }
+        }
+
+        // Beat 4 doesn't exist in 6/8, it carries on into the next bar
+        if MusicalTime::new(3, 4, 0).normalize(&map) != Some(MusicalTime::new(4, 2, 0)) {
+            panic!("2. fail: wrong normalization");
+        }
+
+        // Parses fine, but is past the last tick that fits in a u64
+        let position = "18446744073709551615.1.0".parse::<MusicalTime>().unwrap();
+        if position.to_ticks(&map).is_some() || position.normalize(&map).is_some() || position.snap_to_beat(&map).is_some() {
+            panic!("3. fail: overflow not caught");
+        }
+
+        // With bars of 1 tick, the bar of the last tick doesn't fit in a u64 when counted from 1
+        let one_tick_bars = TimeSignatureMap::new(1, &[(0, TimeSignature::new(1, 4).unwrap())]);
+        if one_tick_bars.ticks_to_bar_beat_tick(u64::MAX) != (u64::MAX, 0, 0) || MusicalTime::from_ticks(u64::MAX, &one_tick_bars).is_some() {
+            panic!("4. fail: overflow not caught");
+        }
+        let last = MusicalTime::new(u64::MAX, 1, 0);
+        if MusicalTime::from_ticks(u64::MAX - 1, &one_tick_bars) != Some(last) || last.to_ticks(&one_tick_bars) != Some(u64::MAX - 1) || last.snap_to_beat(&one_tick_bars) != Some(last) {
+            panic!("4. fail: wrong last bar");
+        }
+        let bars: Vec<(u64, MusicalTime)> = BarLines { map: &one_tick_bars, bar: u64::MAX - 2, end_tick: u64::MAX, done: false }.collect();
+        let beats: Vec<(u64, MusicalTime)> = BeatGrid { map: &one_tick_bars, bar: u64::MAX - 2, beat: 0, end_tick: u64::MAX, done: false }.collect();
+        if bars != vec![(u64::MAX - 2, MusicalTime::new(u64::MAX - 1, 1, 0)), (u64::MAX - 1, last)] || beats != bars {
+            panic!("4. fail: wrong grid {:?} {:?}", bars, beats);
+        }
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let map = map();
+
+        let position = MusicalTime::new(2, 3, 10);
+        if position.add_bars(3) != MusicalTime::new(5, 3, 10) || position.add_bars(-5) != MusicalTime::new(1, 3, 10) {
+            panic!("1. fail: wrong bars");
+        }
+
+        for (position, snapped) in [
+            (MusicalTime::new(1, 2, 47), MusicalTime::new(1, 2, 0)),
+            (MusicalTime::new(1, 2, 48), MusicalTime::new(1, 3, 0)),
+            // The last beat of a bar snaps to the next bar
+            (MusicalTime::new(2, 4, 90), MusicalTime::new(3, 1, 0)),
+            // Dotted quarter beats in 6/8
+            (MusicalTime::new(3, 1, 71), MusicalTime::new(3, 1, 0)),
+            (MusicalTime::new(3, 1, 72), MusicalTime::new(3, 2, 0))
+        ] {
+            if position.snap_to_beat(&map) != Some(snapped) {
+                panic!("2. fail: {} snapped to {:?}", position, position.snap_to_beat(&map));
+            }
+        }
+    }
+
+    #[test]
+    fn test_parsing() {
+        if "12".parse::<MusicalTime>() != Ok(MusicalTime::new(12, 1, 0)) || " 12:3:240 ".parse::<MusicalTime>() != Ok(MusicalTime::new(12, 3, 240)) {
+            panic!("1. fail: short forms");
+        }
+
+        for (text, error) in [("0.1.0", MusicalTimeParseError::ZeroBarOrBeat), ("1.2.3.4", MusicalTimeParseError::InvalidFormat)] {
+            if text.parse::<MusicalTime>() != Err(error) {
+                panic!("2. fail: {} accepted", text);
+            }
+        }
+        for text in ["", "1..0", "a.1.0", "1.-1.0"] {
+            if !matches!(text.parse::<MusicalTime>(), Err(MusicalTimeParseError::InvalidNumber(_))) {
+                panic!("2. fail: {:?} accepted", text);
+            }
+        }
+    }
+
+    #[test]
+    fn test_grid() {
+        let map = map();
+
+        let bars: Vec<(u64, u64)> = bar_lines(&map, 1632).map(|(tick, p)| (tick, p.bar)).collect();
+        if bars != vec![(0, 1), (384, 2), (768, 3), (1056, 4), (1344, 5), (1632, 6)] {
+            panic!("1. fail: wrong bar lines {:?}", bars);
+        }
+
+        let beats: Vec<(u64, String)> = beat_grid(&map, 1440).map(|(tick, p)| (tick, p.to_string())).collect();
+        let expected: Vec<(u64, String)> = [
+            (0, "1.1.0"), (96, "1.2.0"), (192, "1.3.0"), (288, "1.4.0"),
+            (384, "2.1.0"), (480, "2.2.0"), (576, "2.3.0"), (672, "2.4.0"),
+            (768, "3.1.0"), (912, "3.2.0"), (1056, "4.1.0"), (1200, "4.2.0"),
+            (1344, "5.1.0"), (1440, "5.2.0")
+        ].into_iter().map(|(tick, p)| (tick, p.to_string())).collect();
+        if beats != expected {
+            panic!("2. fail: wrong beats {:?}", beats);
+        }
+
+        // A bar cut short by a time signature change has only the beats that fit
+        let interrupted = TimeSignatureMap::new(96, &[(480, TimeSignature::new(3, 4).unwrap())]);
+        let beats: Vec<u64> = beat_grid(&interrupted, 600).map(|(tick, _)| tick).collect();
+        if beats != vec![0, 96, 192, 288, 384, 480, 576] {
+            panic!("3. fail: wrong beats {:?}", beats);
+        }
+
+        // The grids stop at the last bar and beat that fit in a u64, starting two bars before them
+        let last_bar = (u64::MAX - 1344) / 288 + 4;
+        let last_bar_start = map.bar_start_tick(last_bar).unwrap();
+        let bars: Vec<u64> = BarLines { map: &map, bar: last_bar - 2, end_tick: u64::MAX, done: false }.map(|(tick, _)| tick).collect();
+        if bars != vec![last_bar_start - 576, last_bar_start - 288, last_bar_start] {
+            panic!("4. fail: wrong bar lines {:?}", bars);
+        }
+        let beats: Vec<u64> = BeatGrid { map: &map, bar: last_bar - 1, beat: 0, end_tick: u64::MAX, done: false }.map(|(tick, _)| tick).collect();
+        if beats != vec![last_bar_start - 288, last_bar_start - 192, last_bar_start - 96, last_bar_start, last_bar_start + 96, last_bar_start + 192] {
+            panic!("4. fail: wrong beats {:?}", beats);
+        }
+    }
+
+    #[test]
+    fn test_test_midis() {
+        for (name, data) in crate::parser::read_test_midis() {
+            let midi_file = match crate::parser::parse_midi_file(&data) {
+                Ok(f) => f,
+                Err(e) => panic!("{name}: {e}")
+            };
+
+            let map = match TimeSignatureMap::from_midi_file(&midi_file) {
+                Some(m) => m,
+                None => panic!("{name}: no time signature map")
+            };
+            let end_tick = super::super::tempo_map::TempoMap::from_midi_file(&midi_file).end_tick();
+
+            for (tick, position) in beat_grid(&map, end_tick) {
+                if position.to_ticks(&map) != Some(tick) || MusicalTime::from_ticks(tick, &map) != Some(position) {
+                    panic!("{name}: beat {} at tick {}", position, tick);
+                }
+            }
+            let last_bar = MusicalTime::from_ticks(end_tick, &map).unwrap().bar;
+            if bar_lines(&map, end_tick).count() as u64 != last_bar {
+                panic!("{name}: wrong number of bars");
+            }
+        }
+    }
+
+}
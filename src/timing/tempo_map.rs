@@ -0,0 +1,265 @@
+use crate::parser::chunk;
+use crate::parser::meta_event;
+
+// 120 beats per minute, the tempo until the first SetTempo event.
+pub const DEFAULT_MICROSECONDS_PER_QUARTER_NOTE: u64 = 500_000;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TempoChange {
+    pub tick: u64,
+    pub microseconds_per_midi_quarter_note: u64,
+    // The time of the change since the start of the song
+    pub microseconds: f64
+}
+
+// Converts absolute ticks to wall-clock time and back.
+// For SMPTE divisions the ticks have a fixed duration and the SetTempo events are ignored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TempoMap {
+    division: chunk::Division,
+    // Sorted by tick, the first change is always at tick 0
+    changes: Vec<TempoChange>,
+    end_tick: u64
+}
+
+fn tempo_events(events: &[chunk::TrackEvent], start_tick: u64) -> Vec<(u64, u64)> {
+    let mut tick = start_tick;
+    let mut tempos = Vec::<(u64, u64)>::new();
+
+    for event in events {
+        tick += event.delta_time as u64;
+
+        if let chunk::TrackEventType::Meta(meta_event::MetaEvent::SetTempo { microseconds_per_midi_quarter_note }) = event.event {
+            tempos.push((tick, microseconds_per_midi_quarter_note));
+        }
+    }
+
+    tempos
+}
+
+impl TempoMap {
+    // `tempos` are (tick, microseconds per quarter note) pairs in any order, for equal ticks the last one wins.
+    // A tempo of 0 would stop the time, it's ignored and the previous tempo stays.
+    pub fn new(division: chunk::Division, tempos: &[(u64, u64)], end_tick: u64) -> Self {
+        let mut sorted = tempos.to_vec();
+        sorted.sort_by_key(|(tick, _)| *tick);
+
+        let mut changes = vec![TempoChange {
+            tick: 0,
+            microseconds_per_midi_quarter_note: DEFAULT_MICROSECONDS_PER_QUARTER_NOTE,
+            microseconds: 0.0
+        }];
+
+        for (tick, microseconds_per_midi_quarter_note) in sorted {
+            if microseconds_per_midi_quarter_note == 0 {
+                continue;
+            }
+
+            let last = changes[changes.len() - 1];
+
+            if last.tick == tick {
+                let last_idx = changes.len() - 1;
+                changes[last_idx].microseconds_per_midi_quarter_note = microseconds_per_midi_quarter_note;
+                continue;
+            }
+
+            let microseconds = last.microseconds + (tick - last.tick) as f64 * division.microseconds_per_tick(last.microseconds_per_midi_quarter_note);
+            changes.push(TempoChange { tick, microseconds_per_midi_quarter_note, microseconds });
+        }
+
+        Self { division, changes, end_tick }
+    }
+
+    // Format 0 and 1 files share one tempo map between all of the tracks.
+    // Format 2 tracks are independent sequences played one after another, each one starting at the default tempo.
+    pub fn from_midi_file(midi_file: &chunk::MidiFile) -> Self {
+        let division = midi_file.header.division;
+        let mut tempos = Vec::<(u64, u64)>::new();
+        let mut end_tick: u64 = 0;
+
+        match midi_file.header.format {
+            chunk::MidiFileFormat::SequentialTracks => {
+                for track in &midi_file.tracks {
+                    tempos.push((end_tick, DEFAULT_MICROSECONDS_PER_QUARTER_NOTE));
+                    tempos.extend(tempo_events(track.events(), end_tick));
+                    end_tick += track.end_tick();
+                }
+            },
+            _ => {
+                for track in &midi_file.tracks {
+                    tempos.extend(tempo_events(track.events(), 0));
+                    end_tick = end_tick.max(track.end_tick());
+                }
+            }
+        }
+
+        Self::new(division, &tempos, end_tick)
+    }
+
+    // The tempo map that applies to the ticks of a single track, starting at 0 at the beginning of the track.
+    pub fn for_track(midi_file: &chunk::MidiFile, track_index: usize) -> Option<Self> {
+        let track = midi_file.tracks.get(track_index)?;
+
+        match midi_file.header.format {
+            chunk::MidiFileFormat::SequentialTracks => Some(Self::new(midi_file.header.division, &tempo_events(track.events(), 0), track.end_tick())),
+            _ => Some(Self::from_midi_file(midi_file))
+        }
+    }
+
+    pub fn division(&self) -> chunk::Division {
+        self.division
+    }
+
+    pub fn changes(&self) -> &[TempoChange] {
+        &self.changes
+    }
+
+    // The tick of the last event of the song.
+    pub fn end_tick(&self) -> u64 {
+        self.end_tick
+    }
+
+    fn change_at_tick(&self, tick: u64) -> &TempoChange {
+        let idx = self.changes.partition_point(|c| c.tick <= tick);
+        &self.changes[idx.max(1) - 1]
+    }
+
+    pub fn tempo_at(&self, tick: u64) -> u64 {
+        self.change_at_tick(tick).microseconds_per_midi_quarter_note
+    }
+
+    pub fn ticks_to_microseconds(&self, tick: u64) -> f64 {
+        let change = self.change_at_tick(tick);
+        change.microseconds + (tick - change.tick) as f64 * self.division.microseconds_per_tick(change.microseconds_per_midi_quarter_note)
+    }
+
+    pub fn ticks_to_seconds(&self, tick: u64) -> f64 {
+        self.ticks_to_microseconds(tick) / 1_000_000.0
+    }
+
+    // The result is fractional, round it to get the nearest tick.
+    pub fn microseconds_to_ticks(&self, microseconds: f64) -> f64 {
+        let idx = self.changes.partition_point(|c| c.microseconds <= microseconds);
+        let change = &self.changes[idx.max(1) - 1];
+
+        change.tick as f64 + (microseconds - change.microseconds) / self.division.microseconds_per_tick(change.microseconds_per_midi_quarter_note)
+    }
+
+    pub fn seconds_to_ticks(&self, seconds: f64) -> f64 {
+        self.microseconds_to_ticks(seconds * 1_000_000.0)
+    }
+
+    pub fn duration_microseconds(&self) -> f64 {
+        self.ticks_to_microseconds(self.end_tick)
+    }
+
+    pub fn duration_seconds(&self) -> f64 {
+        self.ticks_to_seconds(self.end_tick)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn track(events: Vec<(u32, meta_event::MetaEvent)>) -> chunk::Track {
+        chunk::Track::new(events.into_iter().map(|(delta_time, e)| chunk::TrackEvent {
+            delta_time,
+            event: chunk::TrackEventType::Meta(e),
+            encoding: None
+        }).collect())
+    }
+
+    fn midi_file(format: chunk::MidiFileFormat, division: chunk::Division, tracks: Vec<chunk::Track>) -> chunk::MidiFile {
+        chunk::MidiFile {
+            header: chunk::Header { format, number_of_tracks: tracks.len() as u16, division },
+            tracks,
+            unknown_chunks: vec![],
+            encoding: None
+        }
+    }
+
+    #[test]
+    fn test_ticks_per_quarter_note() {
+        // 120 BPM for 2 quarter notes, then 240 BPM for 2 quarter notes
+        let tempo_map = TempoMap::new(chunk::Division::TicksPerQuarterNote(96), &[(192, 250000)], 384);
+
+        if tempo_map.ticks_to_microseconds(96) != 500000.0 || tempo_map.ticks_to_microseconds(192) != 1000000.0 || tempo_map.ticks_to_microseconds(288) != 1250000.0 {
+            panic!("1. fail: wrong times");
+        }
+        if tempo_map.duration_seconds() != 1.5 || tempo_map.tempo_at(191) != 500000 || tempo_map.tempo_at(192) != 250000 {
+            panic!("1. fail: wrong duration");
+        }
+        if tempo_map.seconds_to_ticks(0.5) != 96.0 || tempo_map.seconds_to_ticks(1.25) != 288.0 || tempo_map.microseconds_to_ticks(2000000.0) != 576.0 {
+            panic!("1. fail: wrong ticks");
+        }
+    }
+
+    #[test]
+    fn test_zero_tempo() {
+        let tempo_map = TempoMap::new(chunk::Division::TicksPerQuarterNote(96), &[(0, 0), (192, 250000), (288, 0)], 384);
+
+        if tempo_map.changes().len() != 2 || tempo_map.tempo_at(0) != DEFAULT_MICROSECONDS_PER_QUARTER_NOTE || tempo_map.tempo_at(300) != 250000 {
+            panic!("1. fail: tempo of 0 kept");
+        }
+        if tempo_map.duration_seconds() != 1.5 || tempo_map.seconds_to_ticks(1.25) != 288.0 {
+            panic!("2. fail: wrong times");
+        }
+    }
+
+    #[test]
+    fn test_smpte() {
+        // 25 fps, 40 ticks per frame - a tick is a millisecond, whatever the tempo
+        let division = chunk::Division::SMPTE { format: chunk::SMPTEFormat::Fps25, ticks_per_frame: 40 };
+        let tempo_map = TempoMap::new(division, &[(100, 250000)], 2000);
+
+        if tempo_map.ticks_to_microseconds(1500) != 1500000.0 || tempo_map.duration_seconds() != 2.0 || tempo_map.seconds_to_ticks(0.75) != 750.0 {
+            panic!("1. fail: wrong times");
+        }
+    }
+
+    #[test]
+    fn test_formats() {
+        let tracks = vec![
+            track(vec![(0, meta_event::MetaEvent::SetTempo { microseconds_per_midi_quarter_note: 1000000 }), (96, meta_event::MetaEvent::EndOfTrack)]),
+            track(vec![(192, meta_event::MetaEvent::EndOfTrack)])
+        ];
+
+        // The tempo of the first track applies to the second one as well, the song ends with the longest track
+        let simultaneous = TempoMap::from_midi_file(&midi_file(chunk::MidiFileFormat::SimultaneousTracks, chunk::Division::TicksPerQuarterNote(96), tracks.clone()));
+        if simultaneous.end_tick() != 192 || simultaneous.duration_seconds() != 2.0 {
+            panic!("1. fail: wrong duration ({})", simultaneous.duration_seconds());
+        }
+
+        // The second track starts after the first one at the default tempo
+        let sequential_file = midi_file(chunk::MidiFileFormat::SequentialTracks, chunk::Division::TicksPerQuarterNote(96), tracks);
+        let sequential = TempoMap::from_midi_file(&sequential_file);
+        if sequential.end_tick() != 288 || sequential.duration_seconds() != 2.0 {
+            panic!("2. fail: wrong duration ({})", sequential.duration_seconds());
+        }
+        match TempoMap::for_track(&sequential_file, 1) {
+            Some(t) => if t.duration_seconds() != 1.0 || t.tempo_at(0) != DEFAULT_MICROSECONDS_PER_QUARTER_NOTE {
+                panic!("2. fail: wrong track duration ({})", t.duration_seconds());
+            },
+            None => panic!("2. fail: no track")
+        }
+    }
+
+    #[test]
+    fn test_test_midis() {
+        for (name, data) in crate::parser::read_test_midis() {
+            let midi_file = match crate::parser::parse_midi_file(&data) {
+                Ok(f) => f,
+                Err(e) => panic!("{name}: {e}")
+            };
+
+            let tempo_map = TempoMap::from_midi_file(&midi_file);
+            let duration = tempo_map.duration_microseconds();
+            if duration <= 0.0 || (tempo_map.microseconds_to_ticks(duration) - tempo_map.end_tick() as f64).abs() > 1e-6 {
+                panic!("{name}: wrong duration ({duration})");
+            }
+        }
+    }
+
+}
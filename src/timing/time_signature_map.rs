@@ -0,0 +1,255 @@
+use crate::parser::chunk;
+use crate::parser::meta_event;
+use crate::parser::time_signature::TimeSignature;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeSignatureChange {
+    pub tick: u64,
+    // The index of the bar starting at the change, counted from 0
+    pub bar: u64,
+    pub time_signature: TimeSignature
+}
+
+// Converts absolute ticks to positions in bars and beats and back.
+// The beats are the beats of the meter, e.g. two dotted quarter notes in 6/8.
+// A time signature change always starts a new bar, a bar interrupted by a change is shorter than its time signature.
+// Only divisions in ticks per quarter note have bars, there's no time signature map for SMPTE divisions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeSignatureMap {
+    ticks_per_quarter_note: u16,
+    // Sorted by tick, the first change is always at tick 0
+    changes: Vec<TimeSignatureChange>
+}
+
+// `MetaEvent::InvalidTimeSignature` is skipped, the previous time signature stays in effect
+fn time_signature_events(events: &[chunk::TrackEvent], start_tick: u64) -> Vec<(u64, TimeSignature)> {
+    let mut tick = start_tick;
+    let mut time_signatures = Vec::<(u64, TimeSignature)>::new();
+
+    for event in events {
+        tick += event.delta_time as u64;
+
+        if let chunk::TrackEventType::Meta(meta_event::MetaEvent::TimeSignature { signature }) = event.event {
+            time_signatures.push((tick, signature));
+        }
+    }
+
+    time_signatures
+}
+
+impl TimeSignatureMap {
+    // `time_signatures` are (tick, time signature) pairs in any order, for equal ticks the last one wins.
+    // The song starts in 4/4 until the first time signature.
+    pub fn new(ticks_per_quarter_note: u16, time_signatures: &[(u64, TimeSignature)]) -> Self {
+        let mut sorted = time_signatures.to_vec();
+        sorted.sort_by_key(|(tick, _)| *tick);
+
+        let mut changes = vec![TimeSignatureChange { tick: 0, bar: 0, time_signature: TimeSignature::default() }];
+
+        for (tick, time_signature) in sorted {
+            let last = changes[changes.len() - 1];
+
+            if last.tick == tick {
+                let last_idx = changes.len() - 1;
+                changes[last_idx].time_signature = time_signature;
+                continue;
+            }
+
+            let bars = (tick - last.tick).div_ceil(last.time_signature.ticks_per_bar(ticks_per_quarter_note));
+            changes.push(TimeSignatureChange { tick, bar: last.bar + bars, time_signature });
+        }
+
+        Self { ticks_per_quarter_note, changes }
+    }
+
+    // Format 0 and 1 files share the time signatures between all of the tracks.
+    // Format 2 tracks are played one after another, each one starting in 4/4.
+    pub fn from_midi_file(midi_file: &chunk::MidiFile) -> Option<Self> {
+        let ticks_per_quarter_note = midi_file.header.division.ticks_per_quarter_note()?;
+        let mut time_signatures = Vec::<(u64, TimeSignature)>::new();
+
+        match midi_file.header.format {
+            chunk::MidiFileFormat::SequentialTracks => {
+                let mut start_tick: u64 = 0;
+                for track in &midi_file.tracks {
+                    time_signatures.push((start_tick, TimeSignature::default()));
+                    time_signatures.extend(time_signature_events(track.events(), start_tick));
+                    start_tick += track.end_tick();
+                }
+            },
+            _ => {
+                for track in &midi_file.tracks {
+                    time_signatures.extend(time_signature_events(track.events(), 0));
+                }
+            }
+        }
+
+        Some(Self::new(ticks_per_quarter_note, &time_signatures))
+    }
+
+    // The time signature map that applies to the ticks of a single track, starting at 0 at the beginning of the track.
+    pub fn for_track(midi_file: &chunk::MidiFile, track_index: usize) -> Option<Self> {
+        let track = midi_file.tracks.get(track_index)?;
+
+        match midi_file.header.format {
+            chunk::MidiFileFormat::SequentialTracks => {
+                let ticks_per_quarter_note = midi_file.header.division.ticks_per_quarter_note()?;
+                Some(Self::new(ticks_per_quarter_note, &time_signature_events(track.events(), 0)))
+            },
+            _ => Self::from_midi_file(midi_file)
+        }
+    }
+
+    pub fn ticks_per_quarter_note(&self) -> u16 {
+        self.ticks_per_quarter_note
+    }
+
+    pub fn changes(&self) -> &[TimeSignatureChange] {
+        &self.changes
+    }
+
+    fn change_at_tick(&self, tick: u64) -> &TimeSignatureChange {
+        let idx = self.changes.partition_point(|c| c.tick <= tick);
+        &self.changes[idx.max(1) - 1]
+    }
+
+    fn change_at_bar(&self, bar: u64) -> &TimeSignatureChange {
+        let idx = self.changes.partition_point(|c| c.bar <= bar);
+        &self.changes[idx.max(1) - 1]
+    }
+
+    pub fn time_signature_at(&self, tick: u64) -> TimeSignature {
+        self.change_at_tick(tick).time_signature
+    }
+
+    pub fn time_signature_of_bar(&self, bar: u64) -> TimeSignature {
+        self.change_at_bar(bar).time_signature
+    }
+
+    // `None` if the bar starts past the last tick that fits in a u64
+    pub fn bar_start_tick(&self, bar: u64) -> Option<u64> {
+        let change = self.change_at_bar(bar);
+        (bar - change.bar).checked_mul(change.time_signature.ticks_per_bar(self.ticks_per_quarter_note))?.checked_add(change.tick)
+    }
+
+    // (bar, beat, tick in the beat), all counted from 0
+    pub fn ticks_to_bar_beat_tick(&self, tick: u64) -> (u64, u32, u64) {
+        let change = self.change_at_tick(tick);
+        let ticks_per_bar = change.time_signature.ticks_per_bar(self.ticks_per_quarter_note);
+        let ticks_per_beat = change.time_signature.ticks_per_beat(self.ticks_per_quarter_note);
+
+        let elapsed = tick - change.tick;
+        let tick_in_bar = elapsed % ticks_per_bar;
+        // The last beat takes the rest of the bar when the bar isn't a whole number of beats long
+        let beat = (tick_in_bar / ticks_per_beat).min(change.time_signature.beats_per_bar() as u64 - 1);
+
+        (change.bar.saturating_add(elapsed / ticks_per_bar), beat as u32, tick_in_bar - beat * ticks_per_beat)
+    }
+
+    // The inverse of `ticks_to_bar_beat_tick`, beats and ticks past the end of a bar carry on into the next ones.
+    // `None` if the position is past the last tick that fits in a u64.
+    pub fn bar_beat_tick_to_ticks(&self, bar: u64, beat: u32, tick: u64) -> Option<u64> {
+        let time_signature = self.time_signature_of_bar(bar);
+        self.bar_start_tick(bar)?
+            .checked_add(beat as u64 * time_signature.ticks_per_beat(self.ticks_per_quarter_note))?
+            .checked_add(tick)
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn time_signature(numerator: u8, denominator: u32) -> TimeSignature {
+        TimeSignature::new(numerator, denominator).unwrap()
+    }
+
+    #[test]
+    fn test_meter_changes() {
+        // 2 bars of 4/4, 2 bars of 6/8, then 3/4 - 96 ticks per quarter note
+        let map = TimeSignatureMap::new(96, &[(1344, time_signature(3, 4)), (768, time_signature(6, 8))]);
+
+        let changes: Vec<(u64, u64)> = map.changes().iter().map(|c| (c.tick, c.bar)).collect();
+        if changes != vec![(0, 0), (768, 2), (1344, 4)] {
+            panic!("1. fail: wrong changes {:?}", changes);
+        }
+
+        for (tick, expected) in [
+            (0, (0, 0, 0)),
+            (500, (1, 1, 20)),
+            (767, (1, 3, 95)),
+            // 6/8 has two beats of 144 ticks
+            (768, (2, 0, 0)),
+            (1000, (2, 1, 88)),
+            (1343, (3, 1, 143)),
+            (1344, (4, 0, 0)),
+            (1344 + 288 * 10 + 200, (14, 2, 8))
+        ] {
+            if map.ticks_to_bar_beat_tick(tick) != expected {
+                panic!("2. fail: tick {} at {:?}", tick, map.ticks_to_bar_beat_tick(tick));
+            }
+            if map.bar_beat_tick_to_ticks(expected.0, expected.1, expected.2) != Some(tick) {
+                panic!("2. fail: {:?} at tick {:?}", expected, map.bar_beat_tick_to_ticks(expected.0, expected.1, expected.2));
+            }
+        }
+
+        if map.time_signature_of_bar(3) != time_signature(6, 8) || map.time_signature_at(1343) != time_signature(6, 8) || map.bar_start_tick(5) != Some(1632) {
+            panic!("3. fail: wrong bars");
+        }
+        if map.bar_start_tick(u64::MAX).is_some() || map.bar_beat_tick_to_ticks(4, 0, u64::MAX).is_some() {
+            panic!("4. fail: overflow not caught");
+        }
+    }
+
+    #[test]
+    fn test_interrupted_bar() {
+        // 3/4 starting in the middle of the second 4/4 bar
+        let map = TimeSignatureMap::new(96, &[(0, time_signature(2, 2)), (600, time_signature(3, 4))]);
+
+        if map.changes()[0].time_signature != time_signature(2, 2) || map.changes()[1].bar != 2 {
+            panic!("1. fail: wrong changes {:?}", map.changes());
+        }
+        if map.ticks_to_bar_beat_tick(599) != (1, 1, 23) || map.ticks_to_bar_beat_tick(600) != (2, 0, 0) || map.bar_start_tick(3) != Some(888) {
+            panic!("2. fail: wrong positions");
+        }
+    }
+
+    #[test]
+    fn test_formats() {
+        let track = |events: Vec<(u32, meta_event::MetaEvent)>| chunk::Track::new(events.into_iter().map(|(delta_time, e)| chunk::TrackEvent {
+            delta_time,
+            event: chunk::TrackEventType::Meta(e),
+            encoding: None
+        }).collect());
+        let tracks = vec![
+            track(vec![(0, meta_event::MetaEvent::TimeSignature { signature: time_signature(3, 4) }), (576, meta_event::MetaEvent::EndOfTrack)]),
+            track(vec![(384, meta_event::MetaEvent::EndOfTrack)])
+        ];
+        let midi_file = |format: chunk::MidiFileFormat, division: chunk::Division| chunk::MidiFile {
+            header: chunk::Header { format, number_of_tracks: 2, division },
+            tracks: tracks.clone(),
+            unknown_chunks: vec![],
+            encoding: None
+        };
+
+        // The second track is in 3/4 as well
+        let simultaneous = TimeSignatureMap::from_midi_file(&midi_file(chunk::MidiFileFormat::SimultaneousTracks, chunk::Division::TicksPerQuarterNote(96))).unwrap();
+        if simultaneous.ticks_to_bar_beat_tick(384) != (1, 1, 0) {
+            panic!("1. fail: wrong position");
+        }
+
+        // The second track starts at the third bar, in 4/4
+        let sequential_file = midi_file(chunk::MidiFileFormat::SequentialTracks, chunk::Division::TicksPerQuarterNote(96));
+        let sequential = TimeSignatureMap::from_midi_file(&sequential_file).unwrap();
+        if sequential.ticks_to_bar_beat_tick(576 + 384) != (3, 0, 0) || TimeSignatureMap::for_track(&sequential_file, 1).unwrap().changes().len() != 1 {
+            panic!("2. fail: wrong position");
+        }
+
+        let smpte = midi_file(chunk::MidiFileFormat::SimultaneousTracks, chunk::Division::SMPTE { format: chunk::SMPTEFormat::Fps25, ticks_per_frame: 40 });
+        if TimeSignatureMap::from_midi_file(&smpte).is_some() {
+            panic!("3. fail: SMPTE division");
+        }
+    }
+
+}
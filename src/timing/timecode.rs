@@ -0,0 +1,338 @@
+use crate::parser::chunk::{self, SMPTEFormat};
+use crate::parser::meta_event;
+
+use super::tempo_map::TempoMap;
+
+// Drop-frame timecode skips the frame labels 00 and 01 at the start of every minute, except every tenth minute
+const DROPPED_FRAMES_PER_MINUTE: u64 = 2;
+const DROP_FRAME_FRAMES_PER_MINUTE: u64 = 60 * 30 - DROPPED_FRAMES_PER_MINUTE;
+const DROP_FRAME_FRAMES_PER_10_MINUTES: u64 = DROP_FRAME_FRAMES_PER_MINUTE * 10 + DROPPED_FRAMES_PER_MINUTE;
+
+// Fractional frames are stored in hundredths of a frame
+pub const SUBFRAMES_PER_FRAME: u8 = 100;
+
+// An SMPTE timecode, hh:mm:ss:ff plus hundredths of a frame, in one of the four MIDI frame rates.
+// The timecode wraps around after 24 hours.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SMPTETimecode {
+    format: SMPTEFormat,
+    hours: u8,
+    minutes: u8,
+    seconds: u8,
+    frames: u8,
+    subframes: u8
+}
+
+impl SMPTETimecode {
+    pub fn zero(format: SMPTEFormat) -> Self {
+        Self { format, hours: 0, minutes: 0, seconds: 0, frames: 0, subframes: 0 }
+    }
+
+    // `None` if a field is out of range or the frame is skipped by drop-frame timecode
+    pub fn new(format: SMPTEFormat, hours: u8, minutes: u8, seconds: u8, frames: u8, subframes: u8) -> Option<Self> {
+        if hours >= 24 || minutes >= 60 || seconds >= 60 || frames >= format.nominal_frames_per_second() || subframes >= SUBFRAMES_PER_FRAME {
+            return None;
+        }
+        if format.is_drop_frame() && seconds == 0 && frames < DROPPED_FRAMES_PER_MINUTE as u8 && !minutes.is_multiple_of(10) {
+            return None;
+        }
+
+        Some(Self { format, hours, minutes, seconds, frames, subframes })
+    }
+
+    // From the five data bytes of the SMPTEOffset meta-event.
+    // The hour byte is 0rrhhhhh, where rr is the frame rate: 0 = 24, 1 = 25, 2 = 29.97 drop-frame, 3 = 30.
+    pub fn from_raw(hr: u8, mn: u8, se: u8, fr: u8, ff: u8) -> Option<Self> {
+        let format = match (hr >> 5) & 0x03 {
+            0 => SMPTEFormat::Fps24,
+            1 => SMPTEFormat::Fps25,
+            2 => SMPTEFormat::Fps30DropFrame,
+            _ => SMPTEFormat::Fps30
+        };
+        if hr & 0x80 != 0 {
+            return None;
+        }
+
+        Self::new(format, hr & 0x1F, mn, se, fr, ff)
+    }
+
+    // (hr, mn, se, fr, ff)
+    pub fn to_raw(self) -> (u8, u8, u8, u8, u8) {
+        let rate: u8 = match self.format {
+            SMPTEFormat::Fps24 => 0,
+            SMPTEFormat::Fps25 => 1,
+            SMPTEFormat::Fps30DropFrame => 2,
+            SMPTEFormat::Fps30 => 3
+        };
+
+        ((rate << 5) | self.hours, self.minutes, self.seconds, self.frames, self.subframes)
+    }
+
+    // The SMPTEOffset meta-event that starts a track at this timecode
+    pub fn to_meta_event(self) -> meta_event::MetaEvent {
+        let (hour, minute, second, frame, fractional_frames) = self.to_raw();
+        meta_event::MetaEvent::SMPTEOffset { hour, minute, second, frame, fractional_frames }
+    }
+
+    pub fn format(self) -> SMPTEFormat {
+        self.format
+    }
+
+    pub fn hours(self) -> u8 {
+        self.hours
+    }
+
+    pub fn minutes(self) -> u8 {
+        self.minutes
+    }
+
+    pub fn seconds(self) -> u8 {
+        self.seconds
+    }
+
+    pub fn frames(self) -> u8 {
+        self.frames
+    }
+
+    // Hundredths of a frame
+    pub fn subframes(self) -> u8 {
+        self.subframes
+    }
+
+    pub fn frames_per_day(format: SMPTEFormat) -> u64 {
+        match format {
+            SMPTEFormat::Fps30DropFrame => DROP_FRAME_FRAMES_PER_10_MINUTES * 6 * 24,
+            _ => format.nominal_frames_per_second() as u64 * 60 * 60 * 24
+        }
+    }
+
+    // The number of frames since 00:00:00:00, without the skipped labels of drop-frame timecode
+    pub fn frame_number(self) -> u64 {
+        let nominal_frames_per_second = self.format.nominal_frames_per_second() as u64;
+        let total_minutes = self.hours as u64 * 60 + self.minutes as u64;
+        let labels = (total_minutes * 60 + self.seconds as u64) * nominal_frames_per_second + self.frames as u64;
+
+        if self.format.is_drop_frame() {
+            labels - DROPPED_FRAMES_PER_MINUTE * (total_minutes - total_minutes / 10)
+        } else {
+            labels
+        }
+    }
+
+    // Frame numbers past 24 hours wrap around to 00:00:00:00
+    pub fn from_frame_number(format: SMPTEFormat, frame_number: u64) -> Self {
+        let mut labels = frame_number % Self::frames_per_day(format);
+
+        if format.is_drop_frame() {
+            let tens_of_minutes = labels / DROP_FRAME_FRAMES_PER_10_MINUTES;
+            let remainder = labels % DROP_FRAME_FRAMES_PER_10_MINUTES;
+            // The first minute of every ten keeps all of its labels
+            let dropped_minutes = if remainder < DROPPED_FRAMES_PER_MINUTE { 0 } else { (remainder - DROPPED_FRAMES_PER_MINUTE) / DROP_FRAME_FRAMES_PER_MINUTE };
+            labels += DROPPED_FRAMES_PER_MINUTE * (tens_of_minutes * 9 + dropped_minutes);
+        }
+
+        let nominal_frames_per_second = format.nominal_frames_per_second() as u64;
+        let total_seconds = labels / nominal_frames_per_second;
+
+        Self {
+            format,
+            hours: (total_seconds / 3600) as u8,
+            minutes: (total_seconds / 60 % 60) as u8,
+            seconds: (total_seconds % 60) as u8,
+            frames: (labels % nominal_frames_per_second) as u8,
+            subframes: 0
+        }
+    }
+
+    // The time since 00:00:00:00, 29.97 fps frames are slightly longer than 1/30 of a second
+    pub fn to_seconds(self) -> f64 {
+        (self.frame_number() as f64 + self.subframes as f64 / SUBFRAMES_PER_FRAME as f64) / self.format.frames_per_second()
+    }
+
+    // Rounded to the nearest hundredth of a frame, `None` for negative or non-finite times
+    pub fn from_seconds(format: SMPTEFormat, seconds: f64) -> Option<Self> {
+        if !seconds.is_finite() || seconds < 0.0 {
+            return None;
+        }
+
+        let subframes = (seconds * format.frames_per_second() * SUBFRAMES_PER_FRAME as f64).round() as u64;
+        let timecode = Self::from_frame_number(format, subframes / SUBFRAMES_PER_FRAME as u64);
+
+        Some(Self { subframes: (subframes % SUBFRAMES_PER_FRAME as u64) as u8, ..timecode })
+    }
+
+    // The same point in time in another frame rate
+    pub fn convert(self, format: SMPTEFormat) -> Self {
+        // Never `None`, the time is at least 0
+        Self::from_seconds(format, self.to_seconds()).unwrap_or(Self::zero(format))
+    }
+
+    fn seconds_per_day(format: SMPTEFormat) -> f64 {
+        Self::frames_per_day(format) as f64 / format.frames_per_second()
+    }
+
+    // The timecode of an absolute tick for a song starting at `start`, in the frame rate of `start`
+    pub fn from_ticks(tick: u64, tempo_map: &TempoMap, start: Self) -> Self {
+        let seconds = (start.to_seconds() + tempo_map.ticks_to_seconds(tick)) % Self::seconds_per_day(start.format);
+        Self::from_seconds(start.format, seconds).unwrap_or(start)
+    }
+
+    // The inverse of `from_ticks`, round the result to get the nearest tick.
+    // Timecodes before `start` are taken to be past midnight.
+    pub fn to_ticks(self, tempo_map: &TempoMap, start: Self) -> f64 {
+        let elapsed = (self.to_seconds() - start.to_seconds()).rem_euclid(Self::seconds_per_day(self.format));
+        tempo_map.seconds_to_ticks(elapsed)
+    }
+}
+
+impl std::fmt::Display for SMPTETimecode {
+    // hh:mm:ss:ff, with a semicolon before the frames of drop-frame timecode and the hundredths of a frame if there are any
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let separator = if self.format.is_drop_frame() { ';' } else { ':' };
+        write!(f, "{:02}:{:02}:{:02}{}{:02}", self.hours, self.minutes, self.seconds, separator, self.frames)?;
+
+        if self.subframes != 0 {
+            write!(f, ".{:02}", self.subframes)?;
+        }
+
+        Ok(())
+    }
+}
+
+// The SMPTEOffset at the start of the track, `None` if there isn't one or it isn't a valid timecode.
+// The offset has to come before any event with a delta-time.
+pub fn track_start_timecode(track: &chunk::Track) -> Option<SMPTETimecode> {
+    for event in track.events().iter().take_while(|e| e.delta_time == 0) {
+        if let chunk::TrackEventType::Meta(meta_event::MetaEvent::SMPTEOffset { hour, minute, second, frame, fractional_frames }) = event.event {
+            return SMPTETimecode::from_raw(hour, minute, second, frame, fractional_frames);
+        }
+    }
+
+    None
+}
+
+// The timecode at which the song starts, stored in the first track.
+// Format 2 tracks can each have their own offset, see `track_start_timecode`.
+pub fn start_timecode(midi_file: &chunk::MidiFile) -> Option<SMPTETimecode> {
+    track_start_timecode(midi_file.tracks.first()?)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+
+    fn timecode(format: SMPTEFormat, hours: u8, minutes: u8, seconds: u8, frames: u8) -> SMPTETimecode {
+        SMPTETimecode::new(format, hours, minutes, seconds, frames, 0).unwrap()
+    }
+
+    #[test]
+    fn test_frame_numbers() {
+        for (format, text, frame_number) in [
+            (SMPTEFormat::Fps24, "01:00:00:00", 86400),
+            (SMPTEFormat::Fps25, "00:01:02:03", 1553),
+            (SMPTEFormat::Fps30, "00:10:00:00", 18000),
+            (SMPTEFormat::Fps30DropFrame, "00:00:59;29", 1799),
+            (SMPTEFormat::Fps30DropFrame, "00:01:00;02", 1800),
+            (SMPTEFormat::Fps30DropFrame, "00:10:00;00", 17982),
+            (SMPTEFormat::Fps30DropFrame, "01:00:00;00", 107892),
+            (SMPTEFormat::Fps30DropFrame, "23:59:59;29", 2589407)
+        ] {
+            let decoded = SMPTETimecode::from_frame_number(format, frame_number);
+            if decoded.to_string() != text || decoded.frame_number() != frame_number {
+                panic!("1. fail: frame {} at {}", frame_number, decoded);
+            }
+        }
+
+        // Every frame of the first 20 drop-frame minutes round trips
+        for frame_number in 0..DROP_FRAME_FRAMES_PER_10_MINUTES * 2 {
+            let decoded = SMPTETimecode::from_frame_number(SMPTEFormat::Fps30DropFrame, frame_number);
+            let (hr, mn, se, fr, ff) = decoded.to_raw();
+            if decoded.frame_number() != frame_number || SMPTETimecode::from_raw(hr, mn, se, fr, ff) != Some(decoded) {
+                panic!("2. fail: frame {} at {}", frame_number, decoded);
+            }
+        }
+
+        if SMPTETimecode::from_frame_number(SMPTEFormat::Fps25, SMPTETimecode::frames_per_day(SMPTEFormat::Fps25) + 1) != timecode(SMPTEFormat::Fps25, 0, 0, 0, 1) {
+            panic!("3. fail: no wrap around");
+        }
+    }
+
+    #[test]
+    fn test_validation() {
+        // 00:01:00;00 and ;01 don't exist in drop-frame, 00:10:00;00 does
+        if SMPTETimecode::new(SMPTEFormat::Fps30DropFrame, 0, 1, 0, 1, 0).is_some() || SMPTETimecode::new(SMPTEFormat::Fps30DropFrame, 0, 10, 0, 0, 0).is_none() {
+            panic!("1. fail: wrong dropped frames");
+        }
+        if SMPTETimecode::new(SMPTEFormat::Fps25, 0, 0, 0, 25, 0).is_some() || SMPTETimecode::new(SMPTEFormat::Fps24, 24, 0, 0, 0, 0).is_some()
+            || SMPTETimecode::new(SMPTEFormat::Fps30, 0, 0, 0, 0, 100).is_some() {
+            panic!("2. fail: invalid timecode accepted");
+        }
+
+        // 01:02:03:04.05 at 25 fps
+        let decoded = SMPTETimecode::from_raw(0x21, 2, 3, 4, 5).unwrap();
+        if decoded.format() != SMPTEFormat::Fps25 || decoded.hours() != 1 || decoded.to_string() != "01:02:03:04.05" || decoded.to_raw() != (0x21, 2, 3, 4, 5) {
+            panic!("3. fail: wrong raw timecode {:?}", decoded);
+        }
+        if SMPTETimecode::from_raw(0x80, 0, 0, 0, 0).is_some() || SMPTETimecode::from_raw(0x18, 0, 0, 0, 0).is_some() {
+            panic!("3. fail: invalid raw timecode accepted");
+        }
+    }
+
+    #[test]
+    fn test_seconds() {
+        let timecode_25 = SMPTETimecode::new(SMPTEFormat::Fps25, 0, 0, 10, 12, 50).unwrap();
+        if timecode_25.to_seconds() != 10.5 || SMPTETimecode::from_seconds(SMPTEFormat::Fps25, 10.5) != Some(timecode_25) {
+            panic!("1. fail: wrong seconds");
+        }
+
+        // An hour of drop-frame timecode is an hour of real time, give or take 3.6 ms
+        let hour = timecode(SMPTEFormat::Fps30DropFrame, 1, 0, 0, 0);
+        if (hour.to_seconds() - 3600.0).abs() > 0.004 || SMPTETimecode::from_seconds(SMPTEFormat::Fps30DropFrame, hour.to_seconds()) != Some(hour) {
+            panic!("2. fail: wrong drop-frame seconds {}", hour.to_seconds());
+        }
+        if hour.convert(SMPTEFormat::Fps25).to_string() != "00:59:59:24.91" {
+            panic!("2. fail: wrong conversion {}", hour.convert(SMPTEFormat::Fps25));
+        }
+
+        if SMPTETimecode::from_seconds(SMPTEFormat::Fps24, -1.0).is_some() || SMPTETimecode::from_seconds(SMPTEFormat::Fps24, f64::NAN).is_some() {
+            panic!("3. fail: invalid seconds accepted");
+        }
+    }
+
+    #[test]
+    fn test_ticks() {
+        // 96 ticks per quarter note at 120 BPM, a tick is 1/192 of a second
+        let tempo_map = TempoMap::new(chunk::Division::TicksPerQuarterNote(96), &[], 1920);
+        let start = timecode(SMPTEFormat::Fps25, 1, 0, 0, 0);
+
+        let at_tick = SMPTETimecode::from_ticks(960, &tempo_map, start);
+        if at_tick.to_string() != "01:00:05:00" || at_tick.to_ticks(&tempo_map, start) != 960.0 {
+            panic!("1. fail: wrong timecode {}", at_tick);
+        }
+
+        // Songs starting right before midnight carry on into the next day
+        let late = timecode(SMPTEFormat::Fps25, 23, 59, 59, 0);
+        let past_midnight = SMPTETimecode::from_ticks(384, &tempo_map, late);
+        if past_midnight.to_string() != "00:00:01:00" || past_midnight.to_ticks(&tempo_map, late) != 384.0 {
+            panic!("2. fail: wrong timecode {}", past_midnight);
+        }
+
+        let meta = |delta_time: u32, event: meta_event::MetaEvent| chunk::TrackEvent { delta_time, event: chunk::TrackEventType::Meta(event), encoding: None };
+        let mut track = chunk::Track::new(vec![
+            meta(0, meta_event::MetaEvent::TrackName { name: "Picture".into() }),
+            meta(0, start.to_meta_event()),
+            meta(1920, meta_event::MetaEvent::EndOfTrack)
+        ]);
+        if track_start_timecode(&track) != Some(start) {
+            panic!("3. fail: offset not found");
+        }
+
+        // Too late to be an offset
+        track.events_mut()[0].delta_time = 10;
+        if track_start_timecode(&track).is_some() {
+            panic!("3. fail: late offset accepted");
+        }
+    }
+
+}
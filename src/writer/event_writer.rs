@@ -0,0 +1,233 @@
+use std::io::{Error, ErrorKind, Write};
+
+use crate::parser::midi_event;
+use crate::parser::meta_event;
+
+// The largest number that fits in a 4-byte variable-length quantity.
+pub const MAX_VARIABLE_LENGTH: u32 = 0x0FFFFFFF;
+
+pub fn write_variable_length<W: Write>(out: &mut W, value: u32) -> Result<(), Error> {
+    write_variable_length_with_size(out, value, 0)
+}
+
+// Writes at least `size` bytes (up to 4), padding the front with 0x80 bytes like a non-minimal encoding.
+pub fn write_variable_length_with_size<W: Write>(out: &mut W, value: u32, size: u8) -> Result<(), Error> {
+    if value > MAX_VARIABLE_LENGTH {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("{} doesn't fit in a variable-length quantity", value)));
+    }
+
+    let mut bytes = [0u8; 4];
+    let mut count: usize = 0;
+    let mut rest = value;
+
+    // The groups of 7 bits are collected from the least significant one, every byte except the last has the top bit set
+    loop {
+        bytes[3 - count] = (rest & 0b01111111) as u8 | if count == 0 { 0 } else { 0b10000000 };
+        count += 1;
+        rest >>= 7;
+
+        if rest == 0 {
+            break;
+        }
+    }
+
+    while count < (size as usize).min(4) {
+        bytes[3 - count] = 0b10000000;
+        count += 1;
+    }
+
+    out.write_all(&bytes[4 - count..])
+}
+
+pub fn write_length_prefixed_data<W: Write>(out: &mut W, data: &[u8], length_size: u8) -> Result<(), Error> {
+    let length = match u32::try_from(data.len()) {
+        Ok(l) => l,
+        Err(_) => return Err(Error::new(ErrorKind::InvalidInput, format!("{}B of data don't fit in a variable-length quantity", data.len())))
+    };
+
+    write_variable_length_with_size(out, length, length_size)?;
+    out.write_all(data)
+}
+
+pub fn midi_event_status(event: &midi_event::MidiEvent) -> u8 {
+    use midi_event::MidiEvent::*;
+
+    match event {
+        NoteOff { channel, .. } => 0b10000000 | (channel & 0b1111),
+        NoteOn { channel, .. } => 0b10010000 | (channel & 0b1111),
+        PolyphonicKeyPressure { channel, .. } => 0b10100000 | (channel & 0b1111),
+        ControlChange { channel, .. }
+        | LocalControlOff { channel }
+        | LocalControlOn { channel }
+        | AllNotesOff { channel }
+        | OmniModeOff { channel }
+        | OmniModeOn { channel }
+        | MonoModeOn { channel, .. }
+        | PolyModeOn { channel } => 0b10110000 | (channel & 0b1111),
+        ProgramChange { channel, .. } => 0b11000000 | (channel & 0b1111),
+        ChannelPressure { channel, .. } => 0b11010000 | (channel & 0b1111),
+        PitchWheelChange { channel, .. } => 0b11100000 | (channel & 0b1111),
+
+        SystemExclusive { .. } => 0b11110000,
+        SystemExclusiveEscape { .. } => 0b11110111,
+        SongPositionPointer { .. } => 0b11110010,
+        SongSelect { .. } => 0b11110011,
+        TuneRequest => 0b11110110,
+
+        TimingClock => 0b11111000,
+        Start => 0b11111010,
+        Continue => 0b11111011,
+        Stop => 0b11111100,
+        ActiveSensing => 0b11111110,
+        Reset => 0b11111111
+    }
+}
+
+// The data bytes of the event that have to fit in 7 bits, 14-bit values are checked separately
+fn midi_event_data_bytes(event: &midi_event::MidiEvent) -> Vec<u8> {
+    use midi_event::MidiEvent::*;
+
+    match event {
+        NoteOff { key, velocity, .. } | NoteOn { key, velocity, .. } => vec![*key, *velocity],
+        PolyphonicKeyPressure { key, pressure_value, .. } => vec![*key, *pressure_value],
+        ControlChange { controller_number, new_value, .. } => vec![*controller_number, *new_value],
+        ProgramChange { new_program_number, .. } => vec![*new_program_number],
+        ChannelPressure { pressure_value, .. } => vec![*pressure_value],
+        MonoModeOn { number_of_channels, .. } => vec![*number_of_channels],
+        SongSelect { song } => vec![*song],
+        _ => vec![]
+    }
+}
+
+// Rejects events that can't be stored in a MIDI file: channels above 15, data bytes above 0x7F and the Reset message.
+pub fn check_midi_event(event: &midi_event::MidiEvent) -> Result<(), Error> {
+    // FF starts a meta-event inside of a track
+    if let midi_event::MidiEvent::Reset = event {
+        return Err(Error::new(ErrorKind::InvalidInput, "The Reset message can't be stored in a MIDI file"));
+    }
+
+    if let Some(channel) = event.channel() {
+        if channel > 0b1111 {
+            return Err(Error::new(ErrorKind::InvalidInput, format!("Channel {} doesn't fit in 4 bits", channel)));
+        }
+    }
+
+    if let Some(byte) = midi_event_data_bytes(event).into_iter().find(|b| *b > 0b01111111) {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("Data byte {} doesn't fit in 7 bits", byte)));
+    }
+
+    let wide_value = match event {
+        midi_event::MidiEvent::PitchWheelChange { pitch_wheel_value, .. } => Some(*pitch_wheel_value),
+        midi_event::MidiEvent::SongPositionPointer { midi_beats_since_start } => Some(*midi_beats_since_start),
+        _ => None
+    };
+    if let Some(value) = wide_value.filter(|v| *v > 0x3FFF) {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("{} doesn't fit in 14 bits", value)));
+    }
+
+    Ok(())
+}
+
+// Writes the bytes following the status byte, the length of System Exclusive events takes at least `data_length_size` bytes.
+pub fn write_midi_event_data<W: Write>(out: &mut W, event: &midi_event::MidiEvent, data_length_size: u8) -> Result<(), Error> {
+    use midi_event::MidiEvent::*;
+
+    match event {
+        NoteOff { key, velocity, .. } | NoteOn { key, velocity, .. } => out.write_all(&[*key, *velocity]),
+        PolyphonicKeyPressure { key, pressure_value, .. } => out.write_all(&[*key, *pressure_value]),
+        ControlChange { controller_number, new_value, .. } => out.write_all(&[*controller_number, *new_value]),
+        ProgramChange { new_program_number, .. } => out.write_all(&[*new_program_number]),
+        ChannelPressure { pressure_value, .. } => out.write_all(&[*pressure_value]),
+        PitchWheelChange { pitch_wheel_value, .. } => out.write_all(&[(pitch_wheel_value & 0b01111111) as u8, ((pitch_wheel_value >> 7) & 0b01111111) as u8]),
+
+        LocalControlOff { .. } => out.write_all(&[122, 0]),
+        LocalControlOn { .. } => out.write_all(&[122, 127]),
+        AllNotesOff { .. } => out.write_all(&[123, 0]),
+        OmniModeOff { .. } => out.write_all(&[124, 0]),
+        OmniModeOn { .. } => out.write_all(&[125, 0]),
+        MonoModeOn { number_of_channels, .. } => out.write_all(&[126, *number_of_channels]),
+        PolyModeOn { .. } => out.write_all(&[127, 0]),
+
+        SystemExclusive { data } | SystemExclusiveEscape { data } => write_length_prefixed_data(out, data, data_length_size),
+        SongPositionPointer { midi_beats_since_start } => out.write_all(&[(midi_beats_since_start & 0b01111111) as u8, ((midi_beats_since_start >> 7) & 0b01111111) as u8]),
+        SongSelect { song } => out.write_all(&[*song]),
+
+        TuneRequest | TimingClock | Start | Continue | Stop | ActiveSensing | Reset => Ok(())
+    }
+}
+
+pub fn write_midi_event<W: Write>(out: &mut W, event: &midi_event::MidiEvent) -> Result<(), Error> {
+    check_midi_event(event)?;
+
+    out.write_all(&[midi_event_status(event)])?;
+    write_midi_event_data(out, event, 0)
+}
+
+pub fn meta_event_code(event: &meta_event::MetaEvent) -> u8 {
+    use meta_event::MetaEvent::*;
+
+    match event {
+        SequenceNumber { .. } => 0x00,
+        TextEvent { .. } => 0x01,
+        CopyrightNotice { .. } => 0x02,
+        TrackName { .. } => 0x03,
+        InstrumentName { .. } => 0x04,
+        Lyric { .. } => 0x05,
+        Marker { .. } => 0x06,
+        CuePoint { .. } => 0x07,
+        MIDIChannelPrefix { .. } => 0x20,
+        EndOfTrack => 0x2F,
+        SetTempo { .. } => 0x51,
+        SMPTEOffset { .. } => 0x54,
+        TimeSignature { .. } | InvalidTimeSignature { .. } => 0x58,
+        KeySignature { .. } | InvalidKeySignature { .. } => 0x59,
+        SequencerSpecific { .. } => 0x7F,
+        Alien { code, .. } => *code
+    }
+}
+
+// The bytes following the length of the meta-event.
+pub fn meta_event_data(event: &meta_event::MetaEvent) -> Result<Vec<u8>, Error> {
+    use meta_event::MetaEvent::*;
+
+    let data = match event {
+        SequenceNumber { number } => number.to_be_bytes().to_vec(),
+        TextEvent { text } => text.as_bytes().to_vec(),
+        CopyrightNotice { notice } => notice.as_bytes().to_vec(),
+        TrackName { name } => name.as_bytes().to_vec(),
+        InstrumentName { name } => name.as_bytes().to_vec(),
+        Lyric { text } => text.as_bytes().to_vec(),
+        Marker { name } => name.as_bytes().to_vec(),
+        CuePoint { text } => text.as_bytes().to_vec(),
+        MIDIChannelPrefix { channel } => vec![*channel],
+        EndOfTrack => vec![],
+        SetTempo { microseconds_per_midi_quarter_note } => {
+            if *microseconds_per_midi_quarter_note > 0xFFFFFF {
+                return Err(Error::new(ErrorKind::InvalidInput, format!("The tempo {} doesn't fit in 3 bytes", microseconds_per_midi_quarter_note)));
+            }
+            microseconds_per_midi_quarter_note.to_be_bytes()[5..].to_vec()
+        },
+        SMPTEOffset { hour, minute, second, frame, fractional_frames } => vec![*hour, *minute, *second, *frame, *fractional_frames],
+        TimeSignature { signature } => {
+            let (numerator, denominator_exponent, midi_clocks_per_metronome_click, thirty_second_notes_per_midi_quarter_note) = signature.to_raw();
+            vec![numerator, denominator_exponent, midi_clocks_per_metronome_click, thirty_second_notes_per_midi_quarter_note]
+        },
+        InvalidTimeSignature { numerator, denominator_exponent, midi_clocks_per_metronome_click, thirty_second_notes_per_midi_quarter_note } => {
+            vec![*numerator, *denominator_exponent, *midi_clocks_per_metronome_click, *thirty_second_notes_per_midi_quarter_note]
+        },
+        KeySignature { key } => {
+            let (sf, mi) = key.to_raw();
+            vec![sf, mi]
+        },
+        InvalidKeySignature { sf, mi } => vec![*sf, *mi],
+        SequencerSpecific { data } => data.clone(),
+        Alien { data, .. } => data.clone()
+    };
+
+    Ok(data)
+}
+
+pub fn write_meta_event<W: Write>(out: &mut W, event: &meta_event::MetaEvent) -> Result<(), Error> {
+    out.write_all(&[0xFF, meta_event_code(event)])?;
+    write_length_prefixed_data(out, &meta_event_data(event)?, 0)
+}
@@ -0,0 +1,464 @@
+mod event_writer;
+
+use std::io::{Error, ErrorKind, Write};
+
+use crate::parser::{self, chunk, meta_event};
+
+pub use event_writer::{MAX_VARIABLE_LENGTH, write_variable_length, write_variable_length_with_size, check_midi_event, write_midi_event, write_meta_event, meta_event_data};
+use event_writer::*;
+
+fn write_chunk<W: Write>(out: &mut W, chunk_type: &[u8; 4], data: &[u8]) -> Result<(), Error> {
+    let length = match u32::try_from(data.len()) {
+        Ok(l) => l,
+        Err(_) => return Err(Error::new(ErrorKind::InvalidInput, format!("The {} chunk is too long ({}B)", String::from_utf8_lossy(chunk_type), data.len())))
+    };
+
+    out.write_all(chunk_type)?;
+    out.write_all(&length.to_be_bytes())?;
+    out.write_all(data)
+}
+
+fn check_number_of_tracks(header: &chunk::Header, number_of_tracks: usize) -> Result<(), Error> {
+    if header.format == chunk::MidiFileFormat::SingleTrack && number_of_tracks != 1 {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("A single track MIDI file can't have {} tracks", number_of_tracks)));
+    }
+
+    Ok(())
+}
+
+// The number of tracks isn't checked against the format, a lossless file keeps the number it was stored with.
+// `extra_data` is written after the known fields, for headers longer than 6 bytes.
+fn write_header<W: Write>(out: &mut W, header: &chunk::Header, number_of_tracks: usize, extra_data: &[u8]) -> Result<(), Error> {
+    let format_idx: u16 = match header.format {
+        chunk::MidiFileFormat::SingleTrack => 0,
+        chunk::MidiFileFormat::SimultaneousTracks => 1,
+        chunk::MidiFileFormat::SequentialTracks => 2
+    };
+
+    let number_of_tracks = match u16::try_from(number_of_tracks) {
+        Ok(n) => n,
+        Err(_) => return Err(Error::new(ErrorKind::InvalidInput, format!("Too many tracks ({})", number_of_tracks)))
+    };
+
+    let division_word: u16 = match header.division {
+        chunk::Division::TicksPerQuarterNote(ticks) => {
+            if ticks & (1 << 15) != 0 {
+                return Err(Error::new(ErrorKind::InvalidInput, format!("Too many ticks per quarter note ({})", ticks)));
+            }
+            ticks
+        },
+        chunk::Division::SMPTE { format, ticks_per_frame } => ((format.to_raw() as u8 as u16) << 8) | (ticks_per_frame as u16)
+    };
+
+    let mut data = Vec::<u8>::with_capacity(chunk::MTHD_LENGTH + extra_data.len());
+    data.extend_from_slice(&format_idx.to_be_bytes());
+    data.extend_from_slice(&number_of_tracks.to_be_bytes());
+    data.extend_from_slice(&division_word.to_be_bytes());
+    data.extend_from_slice(extra_data);
+
+    write_chunk(out, b"MThd", &data)
+}
+
+// The original data of a meta-event is only used while it still decodes to the same event, edited events are written from their new values.
+fn original_meta_event_data<'a>(event: &meta_event::MetaEvent, encoding: &'a chunk::EventEncoding) -> Option<&'a [u8]> {
+    let original = encoding.original_data.as_deref()?;
+
+    let mut bytes = vec![0xFF, meta_event_code(event)];
+    write_length_prefixed_data(&mut bytes, original, 0).ok()?;
+
+    let mut i: usize = 0;
+    match parser::parse_meta_event_at(&bytes, &mut i) {
+        Ok(e) if e == *event => Some(original),
+        _ => None
+    }
+}
+
+pub fn write_track_event<W: Write>(out: &mut W, event: &chunk::TrackEvent) -> Result<(), Error> {
+    write_track_event_with_running_status(out, event, &mut None)
+}
+
+// Follows the encoding of the event if it has one: the status byte is only omitted if the event was stored with running status
+// and the running status is still the same.
+pub fn write_track_event_with_running_status<W: Write>(out: &mut W, event: &chunk::TrackEvent, running_status: &mut Option<u8>) -> Result<(), Error> {
+    let default_encoding = chunk::EventEncoding::default();
+    let encoding = event.encoding.as_ref().unwrap_or(&default_encoding);
+
+    write_variable_length_with_size(out, event.delta_time, encoding.delta_time_size)?;
+
+    match &event.event {
+        chunk::TrackEventType::Midi(e) => {
+            check_midi_event(e)?;
+
+            let status = midi_event_status(e);
+
+            if status & 0b11110000 != 0b11110000 {
+                if !(encoding.running_status && *running_status == Some(status)) {
+                    out.write_all(&[status])?;
+                }
+                *running_status = Some(status);
+            } else {
+                out.write_all(&[status])?;
+
+                // System Common Messages cancel the running status, System Real-Time Messages don't affect it.
+                if status < 0b11111000 {
+                    *running_status = None;
+                }
+            }
+
+            write_midi_event_data(out, e, encoding.data_length_size)
+        },
+        chunk::TrackEventType::Meta(e) => {
+            // Meta-events cancel any running status
+            *running_status = None;
+
+            let data = match original_meta_event_data(e, encoding) {
+                Some(d) => d.to_vec(),
+                None => meta_event_data(e)?
+            };
+
+            out.write_all(&[0xFF, meta_event_code(e)])?;
+            write_length_prefixed_data(out, &data, encoding.data_length_size)
+        }
+    }
+}
+
+fn write_track<W: Write>(out: &mut W, track: &chunk::Track) -> Result<(), Error> {
+    // The length of the chunk is only known once all of the events are encoded
+    let mut data = Vec::<u8>::new();
+    let mut running_status: Option<u8> = None;
+    for event in track.events() {
+        write_track_event_with_running_status(&mut data, event, &mut running_status)?;
+    }
+
+    write_chunk(out, b"MTrk", &data)
+}
+
+// An unknown chunk can't take the type of a chunk the parser would read as the header or a track.
+fn write_unknown_chunk<W: Write>(out: &mut W, unknown_chunk: &chunk::UnknownChunk) -> Result<(), Error> {
+    if &unknown_chunk.id == b"MThd" || &unknown_chunk.id == b"MTrk" {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("An unknown chunk can't have the type {}", String::from_utf8_lossy(&unknown_chunk.id))));
+    }
+
+    write_chunk(out, &unknown_chunk.id, &unknown_chunk.data)
+}
+
+// Files parsed in lossless mode are written back with their original chunk order, unknown chunks, trailing data
+// and number of tracks in the header; the events follow their own encoding.
+// A file parsed without an MThd chunk (in lenient mode) still gets one at the start, holding the actual number of tracks.
+pub fn write_midi_file<W: Write>(out: &mut W, midi_file: &chunk::MidiFile) -> Result<(), Error> {
+    let encoding = match &midi_file.encoding {
+        Some(e) => e,
+        None => {
+            check_number_of_tracks(&midi_file.header, midi_file.tracks.len())?;
+            write_header(out, &midi_file.header, midi_file.tracks.len(), &[])?;
+
+            for track in &midi_file.tracks {
+                write_track(out, track)?;
+            }
+            for unknown_chunk in &midi_file.unknown_chunks {
+                write_unknown_chunk(out, unknown_chunk)?;
+            }
+
+            return Ok(());
+        }
+    };
+
+    if !encoding.chunks.contains(&chunk::ChunkEncoding::Header) {
+        check_number_of_tracks(&midi_file.header, midi_file.tracks.len())?;
+        write_header(out, &midi_file.header, midi_file.tracks.len(), &[])?;
+    }
+
+    let number_of_tracks = midi_file.header.number_of_tracks as usize;
+
+    let mut tracks = midi_file.tracks.iter();
+    let mut unknown_chunks = midi_file.unknown_chunks.iter();
+
+    for chunk_encoding in &encoding.chunks {
+        match chunk_encoding {
+            chunk::ChunkEncoding::Header => write_header(out, &midi_file.header, number_of_tracks, &encoding.header_extra_data)?,
+            chunk::ChunkEncoding::Track => {
+                if let Some(track) = tracks.next() {
+                    write_track(out, track)?;
+                }
+            },
+            chunk::ChunkEncoding::Unknown => {
+                if let Some(unknown_chunk) = unknown_chunks.next() {
+                    write_unknown_chunk(out, unknown_chunk)?;
+                }
+            }
+        }
+    }
+
+    // Chunks added after parsing
+    for track in tracks {
+        write_track(out, track)?;
+    }
+    for unknown_chunk in unknown_chunks {
+        write_unknown_chunk(out, unknown_chunk)?;
+    }
+
+    out.write_all(&encoding.trailing_data)
+}
+
+pub fn midi_file_to_bytes(midi_file: &chunk::MidiFile) -> Result<Vec<u8>, Error> {
+    let mut out = Vec::<u8>::new();
+    write_midi_file(&mut out, midi_file)?;
+
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+
+    use super::*;
+    use crate::parser::midi_event;
+
+    #[test]
+    fn test_variable_length() {
+        for (value, expected) in [
+            (0x00, vec![0x00]),
+            (0x40, vec![0x40]),
+            (0x7F, vec![0x7F]),
+            (0x80, vec![0x81, 0x00]),
+            (0x2000, vec![0xC0, 0x00]),
+            (0x3FFF, vec![0xFF, 0x7F]),
+            (0x4000, vec![0x81, 0x80, 0x00]),
+            (0x0FFFFFFF, vec![0xFF, 0xFF, 0xFF, 0x7F])
+        ] {
+            let mut out = Vec::<u8>::new();
+            if let Err(e) = write_variable_length(&mut out, value) {
+                panic!("{e}");
+            }
+            if out != expected {
+                panic!("{:X} written as {:X?}", value, out);
+            }
+        }
+
+        let mut out = Vec::<u8>::new();
+        if write_variable_length(&mut out, 0x10000000).is_ok() {
+            panic!("0x10000000 written as {:X?}", out);
+        }
+    }
+
+    #[test]
+    fn test_events() {
+        let events = [
+            (chunk::TrackEventType::Midi(midi_event::MidiEvent::NoteOn { channel: 3, key: 60, velocity: 100 }), vec![0x00, 0x93, 0x3C, 0x64]),
+            (chunk::TrackEventType::Midi(midi_event::MidiEvent::PitchWheelChange { channel: 0, pitch_wheel_value: 0x2000 }), vec![0x00, 0xE0, 0x00, 0x40]),
+            (chunk::TrackEventType::Midi(midi_event::MidiEvent::AllNotesOff { channel: 1 }), vec![0x00, 0xB1, 0x7B, 0x00]),
+            (chunk::TrackEventType::Midi(midi_event::MidiEvent::SystemExclusive { data: vec![0x43, 0x12, 0xF7] }), vec![0x00, 0xF0, 0x03, 0x43, 0x12, 0xF7]),
+            (chunk::TrackEventType::Meta(meta_event::MetaEvent::SetTempo { microseconds_per_midi_quarter_note: 500000 }), vec![0x00, 0xFF, 0x51, 0x03, 0x07, 0xA1, 0x20]),
+            (chunk::TrackEventType::Meta(meta_event::MetaEvent::TrackName { name: "Hi".into() }), vec![0x00, 0xFF, 0x03, 0x02, b'H', b'i']),
+            (chunk::TrackEventType::Meta(meta_event::MetaEvent::EndOfTrack), vec![0x00, 0xFF, 0x2F, 0x00])
+        ];
+
+        for (event, expected) in events {
+            let mut out = Vec::<u8>::new();
+            if let Err(e) = write_track_event(&mut out, &chunk::TrackEvent { delta_time: 0, event, encoding: None }) {
+                panic!("{e}");
+            }
+            if out != expected {
+                panic!("wrong bytes: {:X?}, expected {:X?}", out, expected);
+            }
+        }
+
+        let mut out = Vec::<u8>::new();
+        if write_midi_event(&mut out, &midi_event::MidiEvent::Reset).is_ok() {
+            panic!("Reset written as {:X?}", out);
+        }
+
+        for event in [
+            midi_event::MidiEvent::NoteOn { channel: 3, key: 200, velocity: 100 },
+            midi_event::MidiEvent::ControlChange { channel: 16, controller_number: 7, new_value: 100 },
+            midi_event::MidiEvent::PitchWheelChange { channel: 0, pitch_wheel_value: 0x4000 },
+            midi_event::MidiEvent::SongSelect { song: 0x80 }
+        ] {
+            let mut out = Vec::<u8>::new();
+            match write_track_event(&mut out, &chunk::TrackEvent { delta_time: 0, event: chunk::TrackEventType::Midi(event.clone()), encoding: None }) {
+                Err(e) if e.kind() == ErrorKind::InvalidInput => {},
+                _ => panic!("{:?} written as {:X?}", event, out)
+            }
+        }
+    }
+
+    #[test]
+    fn test_round_trip_test_midis() {
+        for (name, data) in crate::parser::read_test_midis() {
+            let midi_file = match parser::parse_midi_file(&data) {
+                Ok(f) => f,
+                Err(e) => panic!("{name}: {e}")
+            };
+            let written = match midi_file_to_bytes(&midi_file) {
+                Ok(w) => w,
+                Err(e) => panic!("{name}: {e}")
+            };
+            let reparsed = match parser::parse_midi_file(&written) {
+                Ok(f) => f,
+                Err(e) => panic!("{name}: {e}")
+            };
+
+            if reparsed != midi_file {
+                panic!("{name}: the file changed after writing it");
+            }
+        }
+    }
+
+    #[test]
+    fn test_formats() {
+        let track = chunk::Track::new(vec![chunk::TrackEvent {
+            delta_time: 0,
+            event: chunk::TrackEventType::Meta(meta_event::MetaEvent::EndOfTrack),
+            encoding: None
+        }]);
+
+        for (format, number_of_tracks) in [
+            (chunk::MidiFileFormat::SingleTrack, 1),
+            (chunk::MidiFileFormat::SimultaneousTracks, 3),
+            (chunk::MidiFileFormat::SequentialTracks, 2)
+        ] {
+            let midi_file = chunk::MidiFile {
+                header: chunk::Header {
+                    format,
+                    number_of_tracks,
+                    division: chunk::Division::SMPTE { format: chunk::SMPTEFormat::Fps30DropFrame, ticks_per_frame: 80 }
+                },
+                tracks: vec![track.clone(); number_of_tracks as usize],
+                unknown_chunks: vec![],
+                encoding: None
+            };
+
+            let written = match midi_file_to_bytes(&midi_file) {
+                Ok(w) => w,
+                Err(e) => panic!("{e}")
+            };
+            match parser::parse_midi_file(&written) {
+                Ok(f) => if f != midi_file {
+                    panic!("{:?}: the file changed after writing it", format);
+                },
+                Err(e) => panic!("{e}")
+            };
+        }
+
+        // Format 0 can only have a single track
+        let midi_file = chunk::MidiFile {
+            header: chunk::Header::default(),
+            tracks: vec![track.clone(), track],
+            unknown_chunks: vec![],
+            encoding: None
+        };
+        if midi_file_to_bytes(&midi_file).is_ok() {
+            panic!("a single track file with 2 tracks was written");
+        }
+    }
+
+    #[test]
+    fn test_lossless_round_trip() {
+        let data: Vec<u8> = [
+            // MThd claiming 3 tracks
+            &[0x4D, 0x54, 0x68, 0x64, 0x00, 0x00, 0x00, 0x06, 0x00, 0x01, 0x00, 0x03, 0x00, 0x60][..],
+            // An unknown chunk
+            &[0x58, 0x46, 0x49, 0x48, 0x00, 0x00, 0x00, 0x03, 0x01, 0x02, 0x03],
+            // MTrk: non-minimal delta time, running status, text that isn't valid UTF-8, KeySignature,
+            // a System Exclusive event with a non-minimal length
+            &[0x4D, 0x54, 0x72, 0x6B, 0x00, 0x00, 0x00, 0x24,
+                0x80, 0x00, 0x90, 0x3C, 0x40,
+                0x10, 0x3E, 0x40,
+                0x00, 0xFF, 0x03, 0x02, 0x83, 0x65,
+                0x00, 0xFF, 0x59, 0x02, 0xFD, 0x01,
+                0x00, 0xF0, 0x80, 0x02, 0x43, 0xF7,
+                0x00, 0x90, 0x3C, 0x00,
+                0x00, 0xFF, 0x2F, 0x80, 0x80, 0x00],
+            // Trailing garbage
+            &[0x00, 0x00, 0x1A]
+        ].concat();
+
+        let midi_file = match parser::parse_midi_file_lossless(&data) {
+            Ok(f) => f,
+            Err(e) => panic!("{e}")
+        };
+        match midi_file_to_bytes(&midi_file) {
+            Ok(written) => if written != data {
+                panic!("wrong bytes: {:X?}", written);
+            },
+            Err(e) => panic!("{e}")
+        };
+
+        // Editing one event leaves the encoding of the others untouched
+        let mut edited = midi_file.clone();
+        let events = edited.tracks[0].events_mut();
+        events[1].event = chunk::TrackEventType::Midi(midi_event::MidiEvent::NoteOn { channel: 0, key: 0x40, velocity: 0x40 });
+        events[2].event = chunk::TrackEventType::Meta(meta_event::MetaEvent::TrackName { name: "Hi".into() });
+        let mut expected = data.clone();
+        expected[39] = 0x40;
+        expected[45] = b'H';
+        expected[46] = b'i';
+        match midi_file_to_bytes(&edited) {
+            Ok(written) => if written != expected {
+                panic!("wrong bytes after editing: {:X?}", written);
+            },
+            Err(e) => panic!("{e}")
+        };
+
+        // Without the file encoding the header holds the actual number of tracks, the unknown chunk is written after the track
+        // and the trailing data is dropped
+        edited.encoding = None;
+        match midi_file_to_bytes(&edited) {
+            Ok(written) => if written[10..12] != [0x00, 0x01] || written.len() != 14 + 8 + 36 + 11 || written[58..] != data[14..25] {
+                panic!("wrong bytes without encoding: {:X?}", written);
+            },
+            Err(e) => panic!("{e}")
+        };
+
+        // An unknown chunk posing as a track
+        edited.unknown_chunks[0].id = *b"MTrk";
+        if midi_file_to_bytes(&edited).is_ok() {
+            panic!("an unknown chunk of type MTrk was written");
+        }
+    }
+
+    #[test]
+    fn test_lossless_header() {
+        let track: &[u8] = &[b'M', b'T', b'r', b'k', 0, 0, 0, 4, 0x00, 0xFF, 0x2F, 0x00];
+        let lenient_lossless = parser::options::ParseOptions { mode: parser::options::ParseMode::Lenient, lossless: true };
+
+        // Format 0 headers declaring 0 and 2 tracks are written back as they were
+        for number_of_tracks in [0, 2] {
+            let data: Vec<u8> = [&[b'M', b'T', b'h', b'd', 0, 0, 0, 6, 0, 0, 0, number_of_tracks, 0, 0x60][..], track].concat();
+            match parser::parse_midi_file_with_options(&data, &lenient_lossless).map(|(f, _)| midi_file_to_bytes(&f)) {
+                Ok(Ok(written)) => if written != data {
+                    panic!("1. fail: wrong bytes {:X?}", written);
+                },
+                _ => panic!("1. fail: {number_of_tracks} tracks not written back")
+            }
+        }
+
+        // Without an MThd chunk, a header is added in front of the track
+        let midi_file = match parser::parse_midi_file_with_options(track, &lenient_lossless) {
+            Ok((f, _)) => f,
+            Err(e) => panic!("{e}")
+        };
+        match midi_file_to_bytes(&midi_file).map(|written| parser::parse_midi_file(&written)) {
+            Ok(Ok(parsed)) => if parsed.tracks.len() != 1 || parsed.header.number_of_tracks != 1 {
+                panic!("2. fail: wrong file {:?}", parsed);
+            },
+            _ => panic!("2. fail: no header written")
+        }
+    }
+
+    #[test]
+    fn test_lossless_round_trip_test_midis() {
+        for (name, data) in crate::parser::read_test_midis() {
+            let midi_file = match parser::parse_midi_file_lossless(&data) {
+                Ok(f) => f,
+                Err(e) => panic!("{name}: {e}")
+            };
+            match midi_file_to_bytes(&midi_file) {
+                Ok(written) => if written != data {
+                    panic!("{name}: the bytes changed after writing the file");
+                },
+                Err(e) => panic!("{name}: {e}")
+            };
+        }
+    }
+
+}